@@ -1 +1,3 @@
+pub mod hnsw;
+pub mod procedure;
 pub mod retrieve;
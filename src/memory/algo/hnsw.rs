@@ -0,0 +1,536 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use ordered_float::OrderedFloat;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::memory::embedding::EmbeddingVec;
+use crate::memory::embedding::note::{MemoryEmbedding, MemoryEmbeddingVariant};
+use crate::memory::embedding::situation::SituationEmbedding;
+use crate::memory::memory_note::MemoryId;
+
+const MAX_LEVEL: usize = 16;
+
+//纯内存的HNSW近似最近邻索引，使用余弦距离（1 - cos），不依赖任何外部服务
+#[derive(Debug, Clone)]
+pub struct Hnsw {
+    max_neighbors: usize,   //第1层及以上每个节点的最大邻居数（M）
+    max_neighbors_0: usize, //第0层每个节点的最大邻居数（2M）
+    ef_construction: usize,
+    ef_search: usize,
+    level_mult: f64,
+    nodes: HashMap<MemoryId, HnswNode>,
+    entry_point: Option<MemoryId>,
+    max_level: usize,
+    rng: StdRng,
+}
+#[derive(Debug, Clone)]
+struct HnswNode {
+    vector: EmbeddingVec,                //归一化后的向量
+    neighbors: Vec<Vec<MemoryId>>,       //每一层的邻居，长度为节点层数+1
+    linked_from: Vec<HashSet<MemoryId>>, //每一层中以该节点为邻居的节点（反向邻接），删除时只需修补这些节点
+}
+impl HnswNode {
+    fn new(vector: EmbeddingVec, level: usize) -> Self {
+        Self {
+            vector,
+            neighbors: vec![Vec::new(); level + 1],
+            linked_from: vec![HashSet::new(); level + 1],
+        }
+    }
+}
+impl Hnsw {
+    pub fn new(max_neighbors: usize, ef_construction: usize) -> Self {
+        let max_neighbors = max_neighbors.max(2);
+        Self {
+            max_neighbors,
+            max_neighbors_0: max_neighbors * 2,
+            ef_construction: ef_construction.max(max_neighbors),
+            ef_search: ef_construction.max(max_neighbors),
+            level_mult: 1.0 / (max_neighbors as f64).ln(),
+            nodes: HashMap::new(),
+            entry_point: None,
+            max_level: 0,
+            rng: StdRng::seed_from_u64(0x5EED),
+        }
+    }
+    pub fn with_ef_search(mut self, ef_search: usize) -> Self {
+        self.ef_search = ef_search.max(1);
+        self
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    pub fn contains(&self, id: MemoryId) -> bool {
+        self.nodes.contains_key(&id)
+    }
+
+    /// 插入或更新一个向量，零向量和空向量会被忽略
+    pub fn insert(&mut self, id: MemoryId, vector: &EmbeddingVec) {
+        self.remove(id);
+        let vector = match vector.normalize() {
+            Ok(vector) if vector.shape() > 0 && vector.iter().all(|x| x.is_finite()) => vector,
+            _ => return,
+        };
+        let level = ((-self.rng.random::<f64>().max(f64::MIN_POSITIVE).ln() * self.level_mult)
+            as usize)
+            .min(MAX_LEVEL);
+
+        let Some(entry_point) = self.entry_point else {
+            self.nodes.insert(id, HnswNode::new(vector, level));
+            self.entry_point = Some(id);
+            self.max_level = level;
+            return;
+        };
+
+        let mut entry_points = vec![entry_point];
+        for layer in (level + 1..=self.max_level).rev() {
+            entry_points = self.search_layer(&vector, &entry_points, 1, layer)[..1]
+                .iter()
+                .map(|&(_, id)| id)
+                .collect();
+        }
+
+        let mut neighbors = vec![Vec::new(); level + 1];
+        for layer in (0..=level.min(self.max_level)).rev() {
+            let found = self.search_layer(&vector, &entry_points, self.ef_construction, layer);
+            neighbors[layer] = found
+                .iter()
+                .take(self.max_neighbors)
+                .map(|&(_, id)| id)
+                .collect();
+            entry_points = found.into_iter().map(|(_, id)| id).collect();
+        }
+
+        self.nodes.insert(id, HnswNode::new(vector, level));
+        for (layer, layer_neighbors) in neighbors.into_iter().enumerate() {
+            self.set_neighbors(id, layer, layer_neighbors.clone());
+            for neighbor in layer_neighbors {
+                self.connect(neighbor, id, layer);
+            }
+        }
+        if level > self.max_level {
+            self.entry_point = Some(id);
+            self.max_level = level;
+        }
+    }
+
+    /// 删除一个向量，并用被删节点的邻居修补受影响节点的邻接表
+    ///
+    /// 只修补反向邻接中指向被删节点的节点；被删节点是入口时，从它最高层的邻居中选出新的入口。
+    pub fn remove(&mut self, id: MemoryId) -> bool {
+        let Some(removed) = self.nodes.remove(&id) else {
+            return false;
+        };
+        for (layer, removed_neighbors) in removed.neighbors.iter().enumerate() {
+            for neighbor in removed_neighbors {
+                if let Some(node) = self.nodes.get_mut(neighbor) {
+                    node.linked_from[layer].remove(&id);
+                }
+            }
+            for &node_id in &removed.linked_from[layer] {
+                let Some(node) = self.nodes.get(&node_id) else {
+                    continue;
+                };
+                let candidates = node.neighbors[layer]
+                    .iter()
+                    .chain(removed_neighbors)
+                    .copied()
+                    .filter(|&candidate| candidate != id && candidate != node_id)
+                    .collect::<HashSet<_>>();
+                let selected = self.closest(&node.vector, candidates, layer);
+                self.set_neighbors(node_id, layer, selected);
+            }
+        }
+
+        if self.entry_point == Some(id) {
+            let next = removed
+                .neighbors
+                .iter()
+                .rev()
+                .find_map(|layer_neighbors| {
+                    layer_neighbors
+                        .iter()
+                        .filter_map(|&neighbor| {
+                            self.nodes.get(&neighbor).map(|node| (neighbor, node))
+                        })
+                        .max_by(|a, b| {
+                            a.1.neighbors
+                                .len()
+                                .cmp(&b.1.neighbors.len())
+                                .then_with(|| b.0.cmp(&a.0))
+                        })
+                })
+                //被删节点没有可用的邻居时，取层数最高的剩余节点（同层取最小的MemoryId），保证结果可复现
+                .or_else(|| {
+                    self.nodes
+                        .iter()
+                        .max_by(|a, b| {
+                            a.1.neighbors
+                                .len()
+                                .cmp(&b.1.neighbors.len())
+                                .then_with(|| b.0.cmp(a.0))
+                        })
+                        .map(|(&id, node)| (id, node))
+                })
+                .map(|(id, node)| (id, node.neighbors.len() - 1));
+            self.entry_point = next.map(|(id, _)| id);
+            self.max_level = next.map(|(_, level)| level).unwrap_or(0);
+        }
+        true
+    }
+
+    /// 返回与query余弦相似度最高的k个(MemoryId, 相似度)，按相似度降序
+    pub fn search(&self, query: &EmbeddingVec, k: usize) -> Vec<(MemoryId, f32)> {
+        let (Some(entry_point), Ok(query)) = (self.entry_point, query.normalize()) else {
+            return Vec::new();
+        };
+        if k == 0 || !query.iter().all(|x| x.is_finite()) {
+            return Vec::new();
+        }
+        let mut entry_points = vec![entry_point];
+        for layer in (1..=self.max_level).rev() {
+            entry_points = self.search_layer(&query, &entry_points, 1, layer)[..1]
+                .iter()
+                .map(|&(_, id)| id)
+                .collect();
+        }
+        self.search_layer(&query, &entry_points, self.ef_search.max(k), 0)
+            .into_iter()
+            .take(k)
+            .map(|(distance, id)| (id, 1.0 - distance))
+            .collect()
+    }
+
+    fn distance(&self, query: &EmbeddingVec, id: MemoryId) -> f32 {
+        self.nodes
+            .get(&id)
+            .and_then(|node| node.vector.dot(query).ok())
+            .map(|similarity| 1.0 - similarity)
+            .unwrap_or(f32::MAX)
+    }
+
+    //在单层上做贪心的best-first搜索，返回按距离升序的至多ef个结果，结果总是非空
+    fn search_layer(
+        &self,
+        query: &EmbeddingVec,
+        entry_points: &[MemoryId],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<(f32, MemoryId)> {
+        let mut visited = entry_points.iter().copied().collect::<HashSet<_>>();
+        let mut candidates = BinaryHeap::new();
+        let mut found = BinaryHeap::new();
+        for &id in entry_points {
+            let distance = OrderedFloat(self.distance(query, id));
+            candidates.push(Reverse((distance, id)));
+            found.push((distance, id));
+        }
+        while let Some(Reverse((distance, id))) = candidates.pop() {
+            if found.len() >= ef && found.peek().is_some_and(|&(worst, _)| distance > worst) {
+                break;
+            }
+            let Some(neighbors) = self.nodes.get(&id).and_then(|node| node.neighbors.get(layer))
+            else {
+                continue;
+            };
+            for &neighbor in neighbors {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let distance = OrderedFloat(self.distance(query, neighbor));
+                if found.len() < ef || found.peek().is_some_and(|&(worst, _)| distance < worst) {
+                    candidates.push(Reverse((distance, neighbor)));
+                    found.push((distance, neighbor));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        let mut found = found
+            .into_iter()
+            .map(|(distance, id)| (distance.0, id))
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+        found
+    }
+
+    //添加from -> to的连接，超出容量时只保留最近的邻居
+    fn connect(&mut self, from: MemoryId, to: MemoryId, layer: usize) {
+        let Some(neighbors) = self
+            .nodes
+            .get(&from)
+            .and_then(|node| node.neighbors.get(layer))
+        else {
+            return;
+        };
+        if neighbors.contains(&to) {
+            return;
+        }
+        let mut candidates = neighbors.iter().copied().collect::<HashSet<_>>();
+        candidates.insert(to);
+        let selected = self.closest(&self.nodes[&from].vector, candidates, layer);
+        self.set_neighbors(from, layer, selected);
+    }
+
+    //替换id在layer层的邻接表，同时维护反向邻接
+    fn set_neighbors(&mut self, id: MemoryId, layer: usize, neighbors: Vec<MemoryId>) {
+        let Some(old) = self
+            .nodes
+            .get_mut(&id)
+            .and_then(|node| node.neighbors.get_mut(layer))
+            .map(|old| std::mem::replace(old, neighbors.clone()))
+        else {
+            return;
+        };
+        for dropped in old.iter().filter(|n| !neighbors.contains(n)) {
+            if let Some(node) = self.nodes.get_mut(dropped) {
+                node.linked_from[layer].remove(&id);
+            }
+        }
+        for added in neighbors.iter().filter(|n| !old.contains(n)) {
+            if let Some(node) = self.nodes.get_mut(added) {
+                node.linked_from[layer].insert(id);
+            }
+        }
+    }
+
+    fn closest(
+        &self,
+        vector: &EmbeddingVec,
+        candidates: HashSet<MemoryId>,
+        layer: usize,
+    ) -> Vec<MemoryId> {
+        let capacity = if layer == 0 {
+            self.max_neighbors_0
+        } else {
+            self.max_neighbors
+        };
+        let mut scored = candidates
+            .into_iter()
+            .map(|id| (self.distance(vector, id), id))
+            .collect::<Vec<_>>();
+        scored.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+        scored.into_iter().take(capacity).map(|(_, id)| id).collect()
+    }
+}
+impl Default for Hnsw {
+    fn default() -> Self {
+        Self::new(16, 100)
+    }
+}
+
+//建立索引的嵌入向量分量
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmbeddingFacet {
+    Tag,
+    SemanticContent,
+    SituationNarrative,
+}
+
+//按分量分别建立的HNSW索引，以MemoryId为键
+#[derive(Debug, Clone, Default)]
+pub struct MemoryAnnIndex {
+    tag: Hnsw,
+    semantic_content: Hnsw,
+    situation_narrative: Hnsw,
+}
+impl MemoryAnnIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn facet(&self, facet: EmbeddingFacet) -> &Hnsw {
+        match facet {
+            EmbeddingFacet::Tag => &self.tag,
+            EmbeddingFacet::SemanticContent => &self.semantic_content,
+            EmbeddingFacet::SituationNarrative => &self.situation_narrative,
+        }
+    }
+    pub fn insert(&mut self, id: MemoryId, embedding: &MemoryEmbedding) {
+        self.remove(id);
+        self.tag.insert(id, embedding.tag());
+        match embedding.variant() {
+            MemoryEmbeddingVariant::Semantic(sem) => self.semantic_content.insert(id, sem.content()),
+            MemoryEmbeddingVariant::Situation(SituationEmbedding::Specific(specific)) => {
+                self.situation_narrative.insert(id, specific.narrative())
+            }
+            _ => {}
+        }
+    }
+    pub fn remove(&mut self, id: MemoryId) {
+        self.tag.remove(id);
+        self.semantic_content.remove(id);
+        self.situation_narrative.remove(id);
+    }
+    pub fn search(
+        &self,
+        facet: EmbeddingFacet,
+        query: &EmbeddingVec,
+        k: usize,
+    ) -> Vec<(MemoryId, f32)> {
+        self.facet(facet).search(query, k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_vectors(n: usize, dim: usize) -> Vec<(MemoryId, EmbeddingVec)> {
+        let mut rng = StdRng::seed_from_u64(42);
+        (0..n)
+            .map(|_| {
+                let vector = (0..dim)
+                    .map(|_| rng.random_range(-1.0f32..1.0))
+                    .collect::<EmbeddingVec>();
+                (MemoryId::new(), vector)
+            })
+            .collect()
+    }
+
+    fn brute_force(data: &[(MemoryId, EmbeddingVec)], query: &EmbeddingVec) -> MemoryId {
+        data.iter()
+            .max_by(|a, b| {
+                let sa = a.1.cosine_similarity(query).unwrap();
+                let sb = b.1.cosine_similarity(query).unwrap();
+                sa.total_cmp(&sb)
+            })
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_hnsw_finds_nearest() {
+        let data = random_vectors(300, 16);
+        let mut index = Hnsw::default();
+        for (id, vector) in &data {
+            index.insert(*id, vector);
+        }
+        assert_eq!(index.len(), 300);
+
+        let queries = random_vectors(20, 16);
+        let hits = queries
+            .iter()
+            .filter(|(_, query)| index.search(query, 1)[0].0 == brute_force(&data, query))
+            .count();
+        assert!(hits >= 18, "recall too low: {hits}/20");
+
+        let (id, vector) = &data[7];
+        let result = index.search(vector, 3);
+        assert_eq!(result[0].0, *id);
+        assert!((result[0].1 - 1.0).abs() < 1e-4);
+        assert!(result[0].1 >= result[1].1);
+    }
+
+    #[test]
+    fn test_hnsw_remove() {
+        let data = random_vectors(100, 8);
+        let mut index = Hnsw::new(4, 32);
+        for (id, vector) in &data {
+            index.insert(*id, vector);
+        }
+        for (id, _) in &data[..50] {
+            assert!(index.remove(*id));
+        }
+        assert!(!index.remove(data[0].0));
+        assert_eq!(index.len(), 50);
+
+        for (id, vector) in &data[50..] {
+            let result = index.search(vector, 5);
+            assert!(result.iter().all(|(found, _)| !data[..50].iter().any(|(r, _)| r == found)));
+            assert!(result.iter().any(|(found, _)| found == id));
+        }
+
+        for (id, _) in &data[50..] {
+            index.remove(*id);
+        }
+        assert!(index.is_empty());
+        assert!(index.search(&data[0].1, 1).is_empty());
+    }
+
+    #[test]
+    fn test_hnsw_reverse_adjacency() {
+        let data = random_vectors(120, 8);
+        let mut index = Hnsw::new(4, 32);
+        for (id, vector) in &data {
+            index.insert(*id, vector);
+        }
+        //删除入口节点与其他节点，反向邻接始终与正向邻接一致
+        for _ in 0..3 {
+            let entry_point = index.entry_point.unwrap();
+            assert!(index.remove(entry_point));
+        }
+        for (id, _) in data.iter().step_by(3) {
+            index.remove(*id);
+        }
+        for (&id, node) in &index.nodes {
+            assert_eq!(node.neighbors.len(), node.linked_from.len());
+            for (layer, linked_from) in node.linked_from.iter().enumerate() {
+                let expected = index
+                    .nodes
+                    .iter()
+                    .filter(|(_, other)| {
+                        other
+                            .neighbors
+                            .get(layer)
+                            .is_some_and(|neighbors| neighbors.contains(&id))
+                    })
+                    .map(|(&other, _)| other)
+                    .collect::<HashSet<_>>();
+                assert_eq!(linked_from, &expected);
+            }
+        }
+
+        let entry_point = index.entry_point.unwrap();
+        assert!(index.contains(entry_point));
+        assert_eq!(
+            index.nodes[&entry_point].neighbors.len() - 1,
+            index.max_level
+        );
+        let (id, vector) = data.iter().find(|(id, _)| index.contains(*id)).unwrap();
+        assert_eq!(index.search(vector, 1)[0].0, *id);
+    }
+
+    #[test]
+    fn test_cluster_keeps_ann_index_in_sync() {
+        use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+        use crate::memory::embedding::{Embeddable, EmbeddingModel};
+        use crate::memory::memory_cluster::MemoryCluster;
+        use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+        use crate::memory::memory_note::{MemoryNoteBuilder, MemoryType};
+
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let mut cluster = MemoryCluster::new();
+        let notes = ["咖啡", "麻辣烫"]
+            .iter()
+            .map(|content| {
+                MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+                    content.to_string(),
+                    ConceptType::Entity,
+                    format!("{content}的描述"),
+                )))
+                .build()
+                .unwrap()
+                .embed_and_fuse(&model)
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let coffee = notes[0].note().id();
+        cluster.merge(notes);
+        assert_eq!(cluster.ann_index().facet(EmbeddingFacet::Tag).len(), 2);
+
+        let query = model.infer_and_fuse(&["咖啡"]).unwrap();
+        let result = cluster.search_similar(EmbeddingFacet::SemanticContent, &query, 1);
+        assert_eq!(result[0].0, coffee);
+
+        cluster.remove_single_node(coffee);
+        let result = cluster.search_similar(EmbeddingFacet::SemanticContent, &query, 2);
+        assert_eq!(result.len(), 1);
+        assert_ne!(result[0].0, coffee);
+    }
+}
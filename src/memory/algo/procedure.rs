@@ -0,0 +1,557 @@
+//程序性记忆的动作触发：沿指向动作（action）的TrigToAction边，按转移概率排序或抽样出被触发的动作
+//
+//经由抽象情境（触发器）到达的动作会被执行；经由语义记忆或具体情境（某次经历）到达的同一动作不会被触发，
+//只作为角色的自我认知
+use std::collections::{HashMap, HashSet};
+
+use petgraph::Direction;
+use petgraph::visit::EdgeRef;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::memory::algo::retrieve::RetrievalResult;
+use crate::memory::memory_cluster::MemoryCluster;
+use crate::memory::memory_links::MemoryLinkType;
+use crate::memory::memory_links::proc_mem::ProcMemLink;
+use crate::memory::memory_note::proc_mem::Action;
+use crate::memory::memory_note::situation_mem::SituationType;
+use crate::memory::memory_note::{MemoryId, MemoryType};
+
+//动作按到达它的节点类型区分的两种用法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActionOrigin {
+    Triggered,     //由抽象情境触发，进入指导LLM行为的提示词
+    SelfCognition, //由语义记忆或具体情境联想到，作为“我通常会这样做”的自我认知加入上下文
+}
+impl ActionOrigin {
+    /// 从该类型的节点出发到达的动作属于哪一种，程序性记忆不能指向动作
+    pub fn of(mem_type: &MemoryType) -> Option<Self> {
+        match mem_type {
+            MemoryType::Situation(SituationType::AbstractSituation(_)) => Some(Self::Triggered),
+            //具体情境是过去的一次经历，不会再次触发当时的动作
+            MemoryType::Situation(SituationType::SpecificSituation(_)) => Some(Self::SelfCognition),
+            MemoryType::Semantic(_) => Some(Self::SelfCognition),
+            MemoryType::Procedure(_) => None,
+        }
+    }
+}
+
+pub struct ActionFiring {
+    rng: StdRng,
+    max_actions: usize, //一轮中每种用法最多给出的动作数
+}
+impl ActionFiring {
+    pub fn new() -> Self {
+        Self {
+            rng: StdRng::from_os_rng(),
+            max_actions: 3,
+        }
+    }
+    /// 使用固定种子，抽样结果可复现
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+    pub fn with_max_actions(mut self, max_actions: usize) -> Self {
+        self.max_actions = max_actions;
+        self
+    }
+    pub fn max_actions(&self) -> usize {
+        self.max_actions
+    }
+
+    /// 列出被激活的触发器可达的全部动作，按触发概率降序排列（同概率按MemoryId）
+    ///
+    /// 只有抽象情境可以作为触发器，其他节点会被忽略。同一动作被多个触发器指向时，
+    /// 触发概率按各触发器独立触发合并，即 1 - Π(1 - p)。
+    pub fn candidates(cluster: &MemoryCluster, triggers: &[MemoryId]) -> Vec<FiredAction> {
+        Self::reached(cluster, triggers, ActionOrigin::Triggered)
+    }
+
+    /// 被激活的语义记忆与具体情境可达的全部动作，排序与概率合并方式同candidates
+    pub fn self_cognition(cluster: &MemoryCluster, activated: &[MemoryId]) -> Vec<FiredAction> {
+        Self::reached(cluster, activated, ActionOrigin::SelfCognition)
+    }
+
+    /// 触发概率最高的至多max_actions个动作
+    pub fn rank(&self, cluster: &MemoryCluster, triggers: &[MemoryId]) -> Vec<FiredAction> {
+        let mut candidates = Self::candidates(cluster, triggers);
+        candidates.truncate(self.max_actions);
+        candidates
+    }
+
+    /// 按转移概率抽样本轮被触发的动作
+    ///
+    /// 每个触发器独立地沿出边抽取至多一个动作：出边概率之和不足1时，剩余概率表示不触发任何动作，
+    /// 超过1时按比例归一化。被抽中的动作按触发概率降序排列，至多返回max_actions个。
+    pub fn sample(&mut self, cluster: &MemoryCluster, triggers: &[MemoryId]) -> Vec<FiredAction> {
+        let mut fired = Vec::new();
+        for &trigger in triggers {
+            let transitions = match Self::transitions(cluster, trigger) {
+                Some((ActionOrigin::Triggered, transitions)) => transitions,
+                _ => continue,
+            };
+            let total = transitions.iter().map(|&(_, _, prob)| prob).sum::<f32>();
+            if total <= 0.0 {
+                continue;
+            }
+            let mut point = self.rng.random::<f32>() * total.max(1.0);
+            for (action_id, _, prob) in transitions {
+                if point < prob {
+                    if !fired.contains(&action_id) {
+                        fired.push(action_id);
+                    }
+                    break;
+                }
+                point -= prob;
+            }
+        }
+        let mut candidates = Self::candidates(cluster, triggers);
+        candidates.retain(|candidate| fired.contains(&candidate.id));
+        candidates.truncate(self.max_actions);
+        candidates
+    }
+
+    /// 对本轮激活的节点（可混有各类记忆）执行程序性检索：
+    /// 抽象情境触发的动作按概率抽样，语义记忆与具体情境联想到的动作按概率排序，各自至多max_actions个
+    pub fn recall(&mut self, cluster: &MemoryCluster, activated: &[MemoryId]) -> ProceduralRecall {
+        let triggered = self.sample(cluster, activated);
+        let mut self_cognition = Self::self_cognition(cluster, activated);
+        self_cognition.truncate(self.max_actions);
+        ProceduralRecall {
+            triggered,
+            self_cognition,
+        }
+    }
+
+    /// 将检索结果中命中的程序性记忆按到达它的节点分类
+    ///
+    /// 到达一个动作的节点为结果中通过TrigToAction边指向它的其他记忆。只要其中有抽象情境，
+    /// 该动作即为被触发，否则（包括只由语义记忆、具体情境到达或直接命中）只作为自我认知。
+    /// 两组动作都保持在结果中的顺序，概率按到达它的节点合并。
+    pub fn classify(cluster: &MemoryCluster, result: &RetrievalResult) -> ProceduralRecall {
+        let activated = result.ids().collect::<HashSet<_>>();
+        let mut recall = ProceduralRecall::default();
+        for memory in result.memories() {
+            let MemoryType::Procedure(proc) = memory.mem_type() else {
+                continue;
+            };
+            let mut fired = FiredAction {
+                id: memory.id(),
+                action: proc.get_action().clone(),
+                origin: ActionOrigin::SelfCognition,
+                prob: 0.0,
+                triggers: Vec::new(),
+            };
+            let incoming = cluster
+                .node_index(memory.id())
+                .into_iter()
+                .flat_map(|index| cluster.graph().edges_directed(index, Direction::Incoming));
+            for edge in incoming {
+                let MemoryLinkType::Proc(ProcMemLink::TrigToAction(trig)) =
+                    edge.weight().link_type()
+                else {
+                    continue;
+                };
+                let source = &cluster.graph()[edge.source()];
+                if !activated.contains(&source.id()) || trig.get_prob() <= 0.0 {
+                    continue;
+                }
+                if ActionOrigin::of(source.mem_type()) == Some(ActionOrigin::Triggered) {
+                    fired.origin = ActionOrigin::Triggered;
+                }
+                fired.prob = 1.0 - (1.0 - fired.prob) * (1.0 - trig.get_prob());
+                fired.triggers.push(source.id());
+            }
+            match fired.origin {
+                ActionOrigin::Triggered => recall.triggered.push(fired),
+                ActionOrigin::SelfCognition => recall.self_cognition.push(fired),
+            }
+        }
+        recall
+    }
+
+    //sources中属于origin一类的节点可达的全部动作
+    fn reached(
+        cluster: &MemoryCluster,
+        sources: &[MemoryId],
+        origin: ActionOrigin,
+    ) -> Vec<FiredAction> {
+        let mut candidates: HashMap<MemoryId, FiredAction> = HashMap::new();
+        for &source in sources {
+            let transitions = match Self::transitions(cluster, source) {
+                Some((reached_by, transitions)) if reached_by == origin => transitions,
+                _ => continue,
+            };
+            for (action_id, action, prob) in transitions {
+                let candidate = candidates.entry(action_id).or_insert_with(|| FiredAction {
+                    id: action_id,
+                    action: action.clone(),
+                    origin,
+                    prob: 0.0,
+                    triggers: Vec::new(),
+                });
+                if !candidate.triggers.contains(&source) {
+                    candidate.prob = 1.0 - (1.0 - candidate.prob) * (1.0 - prob);
+                    candidate.triggers.push(source);
+                }
+            }
+        }
+        let mut candidates = candidates.into_values().collect::<Vec<_>>();
+        candidates.sort_by(|a, b| b.prob.total_cmp(&a.prob).then_with(|| a.id.cmp(&b.id)));
+        candidates
+    }
+
+    //节点的全部TrigToAction出边，按(动作, 概率)给出，目标不是程序性记忆或概率不为正的边被忽略；
+    //同时给出从该节点出发到达的动作属于哪种用法，节点不存在或不能指向动作时为None
+    fn transitions(
+        cluster: &MemoryCluster,
+        source: MemoryId,
+    ) -> Option<(ActionOrigin, Vec<(MemoryId, &Action, f32)>)> {
+        let index = cluster.node_index(source)?;
+        let graph = cluster.graph();
+        let origin = ActionOrigin::of(graph[index].mem_type())?;
+        let mut transitions = graph
+            .edges_directed(index, Direction::Outgoing)
+            .filter_map(|edge| {
+                let MemoryLinkType::Proc(ProcMemLink::TrigToAction(trig)) =
+                    edge.weight().link_type()
+                else {
+                    return None;
+                };
+                let note = graph.node_weight(edge.target())?;
+                match note.mem_type() {
+                    MemoryType::Procedure(proc) if trig.get_prob() > 0.0 => {
+                        Some((note.id(), proc.get_action(), trig.get_prob()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        //固定顺序，保证同一种子下抽样结果可复现
+        transitions.sort_by_key(|&(id, _, _)| id);
+        Some((origin, transitions))
+    }
+}
+impl Default for ActionFiring {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//被触发的动作，prob为合并后的触发概率，triggers为到达该动作的已激活节点
+#[derive(Debug, Clone, PartialEq)]
+pub struct FiredAction {
+    id: MemoryId,
+    action: Action,
+    origin: ActionOrigin,
+    prob: f32,
+    triggers: Vec<MemoryId>,
+}
+impl FiredAction {
+    pub fn id(&self) -> MemoryId {
+        self.id
+    }
+    pub fn action(&self) -> &Action {
+        &self.action
+    }
+    /// 动作的自然语言描述，用于指导LLM行为的提示词
+    pub fn content(&self) -> &str {
+        self.action.get_content()
+    }
+    pub fn origin(&self) -> ActionOrigin {
+        self.origin
+    }
+    pub fn prob(&self) -> f32 {
+        self.prob
+    }
+    pub fn triggers(&self) -> &[MemoryId] {
+        &self.triggers
+    }
+}
+
+//程序性检索的结果，两组动作在组装提示词时分别呈现
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProceduralRecall {
+    triggered: Vec<FiredAction>,      //需要执行的动作
+    self_cognition: Vec<FiredAction>, //只作为自我认知的动作
+}
+impl ProceduralRecall {
+    pub fn triggered(&self) -> &[FiredAction] {
+        &self.triggered
+    }
+    pub fn self_cognition(&self) -> &[FiredAction] {
+        &self.self_cognition
+    }
+    pub fn is_empty(&self) -> bool {
+        self.triggered.is_empty() && self.self_cognition.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::memory::algo::retrieve::{Provenance, RetrievedMemory};
+    use crate::memory::embedding::Embeddable;
+    use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+    use crate::memory::memory_links::MemoryLink;
+    use crate::memory::memory_links::proc_mem::TrigToAction;
+    use crate::memory::memory_note::MemoryNoteBuilder;
+    use crate::memory::memory_note::proc_mem::{ActionType, ProcMemory};
+    use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+    use crate::memory::memory_note::situation_mem::{
+        AbstractSituation, Context, Environment, Event, SituationType, SpecificSituation,
+    };
+
+    fn trig(from: MemoryId, to: MemoryId, prob: f32) -> MemoryLink {
+        MemoryLink::new(
+            from,
+            to,
+            MemoryLinkType::Proc(ProcMemLink::TrigToAction(TrigToAction::new(prob))),
+        )
+    }
+
+    //triggerA --0.8--> 否认, triggerA --0.2--> 嘲讽, triggerB --0.5--> 嘲讽,
+    //语义记忆“张三”也指向“否认”，但不是触发器
+    fn prepare(model: &BgeSmallZh) -> (MemoryCluster, [MemoryId; 5]) {
+        let ids = [
+            MemoryId::new(),
+            MemoryId::new(),
+            MemoryId::new(),
+            MemoryId::new(),
+            MemoryId::new(),
+        ];
+        let [trigger_a, trigger_b, person, deny, mock] = ids;
+        let event = |action: &str| {
+            MemoryType::Situation(SituationType::AbstractSituation(AbstractSituation::Event(
+                Event {
+                    action: action.to_string(),
+                    action_intensity: 0.5,
+                    initiator: "他人".to_string(),
+                    target: "我".to_string(),
+                },
+            )))
+        };
+        let action = |content: &str| {
+            MemoryType::Procedure(ProcMemory::new(Action::new(
+                content.to_string(),
+                ActionType::new_speak(),
+            )))
+        };
+        let notes = vec![
+            (
+                trigger_a,
+                event("被夸奖"),
+                vec![trig(trigger_a, deny, 0.8), trig(trigger_a, mock, 0.2)],
+            ),
+            (trigger_b, event("被挑衅"), vec![trig(trigger_b, mock, 0.5)]),
+            (
+                person,
+                MemoryType::Semantic(SemMemory::new(
+                    "张三".to_string(),
+                    ConceptType::Entity,
+                    "同学".to_string(),
+                )),
+                vec![trig(person, deny, 1.0)],
+            ),
+            (deny, action("否认自己的关心意图"), vec![]),
+            (mock, action("对他人成果进行贬低"), vec![]),
+        ]
+        .into_iter()
+        .map(|(id, mem_type, links)| {
+            MemoryNoteBuilder::new(mem_type)
+                .id(id)
+                .mem_links(links)
+                .build()
+                .unwrap()
+                .embed_and_fuse(model)
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+        let mut cluster = MemoryCluster::new();
+        cluster.merge(notes);
+        (cluster, ids)
+    }
+
+    #[test]
+    fn test_rank_actions_by_probability() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (cluster, [trigger_a, trigger_b, person, deny, mock]) = prepare(&model);
+
+        let ranked = ActionFiring::new().rank(&cluster, &[trigger_a, trigger_b, person]);
+        assert_eq!(
+            ranked.iter().map(|a| a.id()).collect::<Vec<_>>(),
+            vec![deny, mock]
+        );
+        assert_eq!(ranked[0].content(), "否认自己的关心意图");
+        assert!((ranked[0].prob() - 0.8).abs() < 1e-6);
+        //嘲讽由两个触发器独立触发：1 - 0.8 * 0.5
+        assert!((ranked[1].prob() - 0.6).abs() < 1e-6);
+        assert_eq!(ranked[1].triggers(), &[trigger_a, trigger_b]);
+
+        //语义记忆不是触发器
+        assert!(ActionFiring::candidates(&cluster, &[person]).is_empty());
+        assert_eq!(
+            ActionFiring::new()
+                .with_max_actions(1)
+                .rank(&cluster, &[trigger_a, trigger_b])
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_sample_actions_with_seed() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (cluster, [trigger_a, trigger_b, _, deny, mock]) = prepare(&model);
+
+        let sample = |seed| {
+            ActionFiring::new()
+                .with_seed(seed)
+                .sample(&cluster, &[trigger_a, trigger_b])
+                .iter()
+                .map(|a| a.id())
+                .collect::<Vec<_>>()
+        };
+        //相同种子的抽样结果相同
+        assert_eq!(sample(7), sample(7));
+
+        let mut firing = ActionFiring::new().with_seed(42);
+        let mut counts = HashMap::new();
+        for _ in 0..2000 {
+            for action in firing.sample(&cluster, &[trigger_a]) {
+                *counts.entry(action.id()).or_insert(0) += 1;
+            }
+        }
+        //triggerA的出边概率之和为1，每次恰好触发一个动作
+        assert_eq!(counts.values().sum::<i32>(), 2000);
+        let deny_rate = counts[&deny] as f32 / 2000.0;
+        assert!((deny_rate - 0.8).abs() < 0.05, "{deny_rate}");
+        assert!(counts.contains_key(&mock));
+    }
+
+    #[test]
+    fn test_recall_separates_triggered_and_self_cognition() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (cluster, [trigger_a, trigger_b, person, deny, mock]) = prepare(&model);
+
+        //“张三”联想到的“否认”只作为自我认知；triggerB的出边概率之和不足1，可能不触发
+        let recall = ActionFiring::new()
+            .with_seed(3)
+            .recall(&cluster, &[trigger_b, person]);
+        assert!(recall.triggered().iter().all(|a| a.id() == mock));
+        assert_eq!(
+            recall
+                .self_cognition()
+                .iter()
+                .map(|a| (a.id(), a.origin()))
+                .collect::<Vec<_>>(),
+            vec![(deny, ActionOrigin::SelfCognition)]
+        );
+
+        let hit = |id: MemoryId, score: f32| {
+            RetrievedMemory::new(
+                id,
+                score,
+                cluster.get_node(id).unwrap().mem_type().clone(),
+                Provenance::Association,
+            )
+        };
+        //结果中的“否认”同时由triggerA与“张三”到达，视为被触发；“嘲讽”的触发器不在结果中
+        let result = RetrievalResult::new(vec![
+            hit(trigger_a, 0.9),
+            hit(person, 0.8),
+            hit(deny, 0.7),
+            hit(mock, 0.6),
+        ]);
+        let recall = ActionFiring::classify(&cluster, &result);
+        assert_eq!(
+            recall
+                .triggered()
+                .iter()
+                .map(|a| a.id())
+                .collect::<Vec<_>>(),
+            vec![deny]
+        );
+        assert_eq!(recall.triggered()[0].origin(), ActionOrigin::Triggered);
+        assert!((recall.triggered()[0].prob() - 1.0).abs() < 1e-6);
+        assert_eq!(recall.triggered()[0].triggers().len(), 2);
+        //没有触发器被激活时，由语义记忆到达或直接命中的动作都只作为自我认知
+        let result = RetrievalResult::new(vec![hit(person, 0.8), hit(deny, 0.7), hit(mock, 0.6)]);
+        let recall = ActionFiring::classify(&cluster, &result);
+        assert!(recall.triggered().is_empty());
+        assert_eq!(
+            recall
+                .self_cognition()
+                .iter()
+                .map(|a| a.id())
+                .collect::<Vec<_>>(),
+            vec![deny, mock]
+        );
+    }
+
+    #[test]
+    fn test_specific_situation_is_not_a_trigger() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (mut cluster, [_, _, _, deny, _]) = prepare(&model);
+        //一次被夸奖后否认的具体经历，同样指向“否认”
+        let episode = MemoryId::new();
+        let context = Context::new(
+            None,
+            vec![],
+            vec![],
+            vec![],
+            Environment {
+                atmosphere: "轻松".to_string(),
+                tone: "愉快".to_string(),
+            },
+            vec![],
+        );
+        let note = MemoryNoteBuilder::new(MemoryType::Situation(
+            SpecificSituation::new("上周被同事夸奖后连忙否认".to_string(), Utc::now(), context)
+                .into(),
+        ))
+        .id(episode)
+        .mem_links(vec![trig(episode, deny, 0.9)])
+        .build()
+        .unwrap()
+        .embed_and_fuse(&model)
+        .unwrap();
+        cluster.add_single_node(note).unwrap();
+
+        assert!(ActionFiring::candidates(&cluster, &[episode]).is_empty());
+        let recall = ActionFiring::new()
+            .with_seed(3)
+            .recall(&cluster, &[episode]);
+        assert!(recall.triggered().is_empty());
+        assert_eq!(
+            recall
+                .self_cognition()
+                .iter()
+                .map(|a| (a.id(), a.origin()))
+                .collect::<Vec<_>>(),
+            vec![(deny, ActionOrigin::SelfCognition)]
+        );
+
+        let result = RetrievalResult::new(vec![
+            RetrievedMemory::new(
+                episode,
+                0.9,
+                cluster.get_node(episode).unwrap().mem_type().clone(),
+                Provenance::Association,
+            ),
+            RetrievedMemory::new(
+                deny,
+                0.7,
+                cluster.get_node(deny).unwrap().mem_type().clone(),
+                Provenance::Association,
+            ),
+        ]);
+        let recall = ActionFiring::classify(&cluster, &result);
+        assert!(recall.triggered().is_empty());
+        assert_eq!(recall.self_cognition()[0].id(), deny);
+        assert_eq!(recall.self_cognition()[0].triggers(), &[episode]);
+    }
+}
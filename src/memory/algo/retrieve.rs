@@ -1,9 +1,254 @@
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::memory::embedding::{EmbeddingCalcError, EmbeddingGenError};
+use crate::memory::memory_cluster::{ClusterError, MemoryCluster};
+use crate::memory::memory_note::{MemoryId, MemoryType};
+
 pub mod association;
+pub mod batch;
 pub mod deep_thought;
+pub mod diversity;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod hybrid;
+pub mod rerank;
 pub mod short_only;
 pub mod similarity;
+pub mod spreading;
 
 pub trait RetrStrategy {
     type RetrRequest; //接受的查询参数类型
-    fn retrieve(&self, request: Self::RetrRequest) -> Vec<String>; //TODO：返回类型还没想好，暂定Vec<String>，或许也可以考虑返回迭代器，看具体场景
+    fn retrieve(&self, request: Self::RetrRequest) -> RetrResult<RetrievalResult>;
+}
+
+/// 需要调用LLM等异步资源的检索策略
+#[async_trait]
+pub trait AsyncRetrStrategy {
+    type RetrRequest: Send;
+    async fn retrieve_async(&self, request: Self::RetrRequest) -> RetrResult<RetrievalResult>;
+}
+
+pub type RetrResult<T> = Result<T, RetrError>;
+
+#[derive(Debug, Error)]
+pub enum RetrError {
+    #[error("Query embedding failed")]
+    EmbeddingGen(#[from] EmbeddingGenError),
+    #[error("Score calculation failed")]
+    EmbeddingCalc(#[from] EmbeddingCalcError),
+    #[error("LLM call failed: {0}")]
+    Llm(#[from] anyhow::Error),
+    #[error("Retrieved memories could not be added to the query subgraph: {0:?}")]
+    Subgraph(Vec<ClusterError>),
+}
+
+//记录一条检索结果是由哪种策略得到的
+#[derive(Debug, Clone, PartialEq)]
+pub enum Provenance {
+    Similarity,
+    Association,
+    Spreading,
+    DeepThought { depth: usize }, //在第几层推理中被探索到
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetrievedMemory {
+    id: MemoryId,
+    score: f32,
+    mem_type: MemoryType,
+    provenance: Provenance,
+}
+impl RetrievedMemory {
+    pub fn new(id: MemoryId, score: f32, mem_type: MemoryType, provenance: Provenance) -> Self {
+        Self {
+            id,
+            score,
+            mem_type,
+            provenance,
+        }
+    }
+    pub fn id(&self) -> MemoryId {
+        self.id
+    }
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+    pub fn mem_type(&self) -> &MemoryType {
+        &self.mem_type
+    }
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+}
+
+/// 检索结果，memories总是按分数降序排列（分数相同按MemoryId），short_term为滑动窗口中的原始信息
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetrievalResult {
+    memories: Vec<RetrievedMemory>,
+    short_term: Vec<String>,
+}
+impl RetrievalResult {
+    pub fn new(mut memories: Vec<RetrievedMemory>) -> Self {
+        Self::sort_memories(&mut memories);
+        Self {
+            memories,
+            short_term: Vec::new(),
+        }
+    }
+    pub fn from_short_term(short_term: Vec<String>) -> Self {
+        Self {
+            memories: Vec::new(),
+            short_term,
+        }
+    }
+    /// 由(MemoryId, 分数)构造结果，不在cluster中的节点会被忽略
+    pub fn from_scored(
+        cluster: &MemoryCluster,
+        scored: impl IntoIterator<Item = (MemoryId, f32)>,
+        provenance: Provenance,
+    ) -> Self {
+        let memories = scored
+            .into_iter()
+            .filter_map(|(id, score)| {
+                cluster.get_node(id).map(|note| {
+                    RetrievedMemory::new(id, score, note.mem_type().clone(), provenance.clone())
+                })
+            })
+            .collect();
+        Self::new(memories)
+    }
+    pub fn memories(&self) -> &[RetrievedMemory] {
+        &self.memories
+    }
+    pub fn short_term(&self) -> &[String] {
+        &self.short_term
+    }
+    pub fn into_memories(self) -> Vec<RetrievedMemory> {
+        self.memories
+    }
+    pub fn ids(&self) -> impl Iterator<Item = MemoryId> + '_ {
+        self.memories.iter().map(|memory| memory.id())
+    }
+    pub fn len(&self) -> usize {
+        self.memories.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.memories.is_empty() && self.short_term.is_empty()
+    }
+    pub fn truncate(&mut self, len: usize) {
+        self.memories.truncate(len);
+    }
+    /// 只保留满足条件的记忆，顺序不变
+    pub fn retain(&mut self, f: impl FnMut(&RetrievedMemory) -> bool) {
+        self.memories.retain(f);
+    }
+    /// 等比缩放全部分数使最高分为top，顺序不变；最高分不为正时不做处理
+    pub fn rescale(&mut self, top: f32) {
+        let max = self.memories.first().map_or(0.0, |memory| memory.score);
+        if max <= 0.0 {
+            return;
+        }
+        for memory in &mut self.memories {
+            memory.score *= top / max;
+        }
+    }
+    /// 并入另一组结果，同一记忆保留分数较高的一条（连同其来源），short_term中尚未出现的条目依次拼接
+    pub fn merge(&mut self, other: RetrievalResult) {
+        let mut positions = self
+            .memories
+            .iter()
+            .enumerate()
+            .map(|(i, memory)| (memory.id, i))
+            .collect::<HashMap<_, _>>();
+        for memory in other.memories {
+            match positions.get(&memory.id) {
+                Some(&i) if self.memories[i].score >= memory.score => {}
+                Some(&i) => self.memories[i] = memory,
+                None => {
+                    positions.insert(memory.id, self.memories.len());
+                    self.memories.push(memory);
+                }
+            }
+        }
+        let mut seen = self.short_term.iter().cloned().collect::<HashSet<_>>();
+        self.short_term.extend(
+            other
+                .short_term
+                .into_iter()
+                .filter(|message| seen.insert(message.clone())),
+        );
+        Self::sort_memories(&mut self.memories);
+    }
+    fn sort_memories(memories: &mut [RetrievedMemory]) {
+        memories.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+
+    fn sem_type() -> MemoryType {
+        MemoryType::Semantic(SemMemory::new(
+            "Test".to_string(),
+            ConceptType::Entity,
+            "Test description".to_string(),
+        ))
+    }
+
+    #[test]
+    fn test_retrieval_result_sorted_by_score() {
+        let (a, b, c) = (MemoryId::new(), MemoryId::new(), MemoryId::new());
+        let result = RetrievalResult::new(vec![
+            RetrievedMemory::new(a, 0.2, sem_type(), Provenance::Similarity),
+            RetrievedMemory::new(b, 0.9, sem_type(), Provenance::Association),
+            RetrievedMemory::new(c, 0.5, sem_type(), Provenance::Similarity),
+        ]);
+
+        assert_eq!(result.ids().collect::<Vec<_>>(), vec![b, c, a]);
+        assert_eq!(result.memories()[0].provenance(), &Provenance::Association);
+        assert!(result.short_term().is_empty());
+    }
+
+    #[test]
+    fn test_retrieval_result_from_scored_skips_unknown() {
+        let cluster = MemoryCluster::new();
+        let result = RetrievalResult::from_scored(
+            &cluster,
+            vec![(MemoryId::new(), 1.0)],
+            Provenance::Similarity,
+        );
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_retrieval_result_merge_keeps_best() {
+        let (a, b, c) = (MemoryId::new(), MemoryId::new(), MemoryId::new());
+        let mut result = RetrievalResult::new(vec![
+            RetrievedMemory::new(a, 0.8, sem_type(), Provenance::Similarity),
+            RetrievedMemory::new(b, 0.3, sem_type(), Provenance::Similarity),
+        ]);
+        result.merge(RetrievalResult::new(vec![
+            RetrievedMemory::new(a, 0.1, sem_type(), Provenance::Association),
+            RetrievedMemory::new(b, 0.6, sem_type(), Provenance::Association),
+            RetrievedMemory::new(c, 0.2, sem_type(), Provenance::Association),
+        ]));
+
+        assert_eq!(result.ids().collect::<Vec<_>>(), vec![a, b, c]);
+        assert_eq!(result.memories()[0].provenance(), &Provenance::Similarity);
+        assert_eq!(result.memories()[1].provenance(), &Provenance::Association);
+        assert!((result.memories()[1].score() - 0.6).abs() < 1e-6);
+
+        //两层给出相同的短期信息时只保留一份
+        let mut result = RetrievalResult::from_short_term(vec!["你好".to_string()]);
+        result.merge(RetrievalResult::from_short_term(vec![
+            "你好".to_string(),
+            "在吗".to_string(),
+        ]));
+        assert_eq!(result.short_term(), &["你好", "在吗"]);
+    }
 }
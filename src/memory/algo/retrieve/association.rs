@@ -1,19 +1,309 @@
+use std::collections::VecDeque;
 use std::sync::Arc;
 
+use petgraph::Direction;
+use petgraph::prelude::NodeIndex;
+use petgraph::visit::{EdgeRef, NodeIndexable};
+
+use crate::memory::memory_cluster::MemoryCluster;
+use crate::memory::memory_note::MemoryId;
 use crate::memory::working_memory::WorkingMemory;
 
-use super::RetrStrategy;
+use super::{Provenance, RetrResult, RetrStrategy, RetrievalResult};
 
-//用PPR变种算法进行联想
+//用PPR变种算法进行联想（带边权的EdgePush-PPR）
 pub struct RetrAssociation {
     max_results: usize,
+    teleport_prob: f32,      //每次push时留在当前节点的概率（alpha），其余沿边扩散
+    residual_tolerance: f32, //残差小于此值的节点不再push
 }
+impl RetrAssociation {
+    pub fn new(max_results: usize) -> Self {
+        Self {
+            max_results,
+            teleport_prob: 0.15,
+            residual_tolerance: 1e-4,
+        }
+    }
+    pub fn with_teleport_prob(mut self, teleport_prob: f32) -> Self {
+        self.teleport_prob = teleport_prob.clamp(f32::EPSILON, 1.0);
+        self
+    }
+    pub fn with_residual_tolerance(mut self, residual_tolerance: f32) -> Self {
+        self.residual_tolerance = residual_tolerance.max(f32::EPSILON);
+        self
+    }
+    pub fn max_results(&self) -> usize {
+        self.max_results
+    }
+    pub fn teleport_prob(&self) -> f32 {
+        self.teleport_prob
+    }
+    pub fn residual_tolerance(&self) -> f32 {
+        self.residual_tolerance
+    }
+
+    /// 以seeds为起点，在cluster上执行带边权的Forward Push PPR，返回按分数降序的top-k节点
+    ///
+    /// 边权取自`MemoryLinkType::strength`，无出边（或出边权重和为0）的悬挂节点，
+    /// 其扩散部分会按种子分布传送回种子节点，因此无环子图同样可以收敛。
+    /// 分数相同时按MemoryId排序，保证结果可复现。
+    pub fn personalized_pagerank(
+        &self,
+        cluster: &MemoryCluster,
+        seeds: &[(MemoryId, f32)],
+    ) -> Vec<(MemoryId, f32)> {
+        let graph = cluster.graph();
+
+        //种子分布，忽略不在图中的节点和非正权重，合并重复的种子
+        let mut seed_dist: Vec<(NodeIndex, f32)> = Vec::with_capacity(seeds.len());
+        for &(mem_id, weight) in seeds {
+            if weight <= 0.0 || !weight.is_finite() {
+                continue;
+            }
+            if let Some(index) = cluster.node_index(mem_id) {
+                match seed_dist.iter_mut().find(|(i, _)| *i == index) {
+                    Some((_, w)) => *w += weight,
+                    None => seed_dist.push((index, weight)),
+                }
+            }
+        }
+        let seed_sum = seed_dist.iter().map(|(_, w)| w).sum::<f32>();
+        if seed_dist.is_empty() || seed_sum <= 0.0 {
+            return Vec::new();
+        }
+        seed_dist.iter_mut().for_each(|(_, w)| *w /= seed_sum);
+
+        let bound = graph.node_bound();
+        let out_weight = graph
+            .node_indices()
+            .fold(vec![0.0f32; bound], |mut acc, index| {
+                acc[index.index()] = graph
+                    .edges_directed(index, Direction::Outgoing)
+                    .map(|edge| edge.weight().link_type().strength())
+                    .sum();
+                acc
+            });
+
+        let mut score = vec![0.0f32; bound];
+        let mut residual = vec![0.0f32; bound];
+        let mut in_queue = vec![false; bound];
+        let mut queue = VecDeque::new();
+        for &(index, weight) in &seed_dist {
+            residual[index.index()] = weight;
+            in_queue[index.index()] = true;
+            queue.push_back(index);
+        }
+
+        let alpha = self.teleport_prob;
+        let tolerance = self.residual_tolerance;
+        while let Some(node) = queue.pop_front() {
+            in_queue[node.index()] = false;
+            let node_residual = std::mem::take(&mut residual[node.index()]);
+            if node_residual <= tolerance {
+                continue;
+            }
+            score[node.index()] += alpha * node_residual;
+            let spread = (1.0 - alpha) * node_residual;
+
+            let mut touched = Vec::new();
+            if out_weight[node.index()] > 0.0 {
+                for edge in graph.edges_directed(node, Direction::Outgoing) {
+                    let weight = edge.weight().link_type().strength();
+                    if weight <= 0.0 {
+                        continue;
+                    }
+                    residual[edge.target().index()] += spread * weight / out_weight[node.index()];
+                    touched.push(edge.target());
+                }
+            } else {
+                //悬挂节点：传送回种子节点
+                for &(seed, weight) in &seed_dist {
+                    residual[seed.index()] += spread * weight;
+                    touched.push(seed);
+                }
+            }
+
+            for target in touched {
+                if !in_queue[target.index()] && residual[target.index()] > tolerance {
+                    in_queue[target.index()] = true;
+                    queue.push_back(target);
+                }
+            }
+        }
+
+        let mut ranked = graph
+            .node_indices()
+            .filter(|index| score[index.index()] > 0.0)
+            .filter_map(|index| {
+                graph
+                    .node_weight(index)
+                    .map(|note| (note.id(), score[index.index()]))
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(self.max_results);
+        ranked
+    }
+}
+
 pub struct AssociationRequest {
     working_mem: Arc<WorkingMemory>,
+    seeds: Vec<(MemoryId, f32)>, //种子节点及其权重，通常来自相似度检索
+}
+impl AssociationRequest {
+    pub fn new(working_mem: Arc<WorkingMemory>, seeds: Vec<(MemoryId, f32)>) -> Self {
+        Self { working_mem, seeds }
+    }
 }
 impl RetrStrategy for RetrAssociation {
     type RetrRequest = AssociationRequest;
-    fn retrieve(&self, request: Self::RetrRequest) -> Vec<String> {
-        todo!()
+    fn retrieve(&self, request: Self::RetrRequest) -> RetrResult<RetrievalResult> {
+        let cluster = request.working_mem.cluster();
+        let ranked = self.personalized_pagerank(cluster, &request.seeds);
+        Ok(RetrievalResult::from_scored(
+            cluster,
+            ranked,
+            Provenance::Association,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::embedding::Embeddable;
+    use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+    use crate::memory::memory_links::sem_mem::SemMemLink;
+    use crate::memory::memory_links::{MemoryLink, MemoryLinkType};
+    use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+    use crate::memory::memory_note::{MemoryNoteBuilder, MemoryType};
+
+    fn sem_link(from: MemoryId, to: MemoryId, intensity: f32) -> MemoryLink {
+        MemoryLink::new(
+            from,
+            to,
+            MemoryLinkType::Sem(SemMemLink::new("关联".to_string(), intensity, 1.0)),
+        )
+    }
+
+    fn build_cluster(
+        model: &BgeSmallZh,
+        nodes: &[(MemoryId, &str)],
+        links: Vec<MemoryLink>,
+    ) -> MemoryCluster {
+        let mut cluster = MemoryCluster::new();
+        let notes = nodes
+            .iter()
+            .map(|&(id, content)| {
+                let mut memory = SemMemory::new(
+                    content.to_string(),
+                    ConceptType::Entity,
+                    format!("{content}的描述"),
+                );
+                memory.aliases = vec![content.to_string()];
+                let node_links = links
+                    .iter()
+                    .filter(|link| link.from() == id)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                MemoryNoteBuilder::new(MemoryType::Semantic(memory))
+                    .id(id)
+                    .tags(vec!["测试".to_string()])
+                    .mem_links(node_links)
+                    .build()
+                    .unwrap()
+                    .embed_and_fuse(model)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        cluster.merge(notes);
+        cluster
+    }
+
+    #[test]
+    fn test_ppr_chain_decays_with_distance() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (a, b, c) = (MemoryId::new(), MemoryId::new(), MemoryId::new());
+        let cluster = build_cluster(
+            &model,
+            &[(a, "咖啡"), (b, "咖啡机"), (c, "礼物")],
+            vec![sem_link(a, b, 1.0), sem_link(b, c, 1.0)],
+        );
+
+        let retr = RetrAssociation::new(10);
+        let ranked = retr.personalized_pagerank(&cluster, &[(a, 1.0)]);
+        assert_eq!(
+            ranked.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![a, b, c]
+        );
+        //结果可复现
+        assert_eq!(ranked, retr.personalized_pagerank(&cluster, &[(a, 1.0)]));
+    }
+
+    #[test]
+    fn test_ppr_prefers_stronger_links() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (a, b, c) = (MemoryId::new(), MemoryId::new(), MemoryId::new());
+        let cluster = build_cluster(
+            &model,
+            &[(a, "张三"), (b, "李四"), (c, "王五")],
+            vec![sem_link(a, b, 1.0), sem_link(a, c, 0.1)],
+        );
+
+        let ranked = RetrAssociation::new(10).personalized_pagerank(&cluster, &[(a, 1.0)]);
+        let score_of = |id| ranked.iter().find(|(i, _)| *i == id).unwrap().1;
+        assert!(score_of(b) > score_of(c));
+    }
+
+    #[test]
+    fn test_activation_paths_for_ppr_results() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (a, b, c, d) = (MemoryId::new(), MemoryId::new(), MemoryId::new(), MemoryId::new());
+        let (ab, bc, ac) = (sem_link(a, b, 1.0), sem_link(b, c, 0.9), sem_link(a, c, 0.1));
+        let link_ids = (ab.id(), bc.id());
+        let cluster = build_cluster(
+            &model,
+            &[(a, "朋友"), (b, "咖啡"), (c, "咖啡机"), (d, "文玩")],
+            vec![ab, bc, ac],
+        );
+
+        let ranked = RetrAssociation::new(10).personalized_pagerank(&cluster, &[(a, 1.0)]);
+        let targets = ranked.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let paths = cluster.activation_paths(&[a], &targets);
+        assert_eq!(paths.len(), 3);
+
+        let to_c = paths.iter().find(|path| path.target() == c).unwrap();
+        //1.0 * 0.9 的路径强于直连的0.1
+        assert_eq!(to_c.links(), &[link_ids.0, link_ids.1]);
+        let to_a = paths.iter().find(|path| path.target() == a).unwrap();
+        assert!(to_a.links().is_empty());
+
+        assert!(cluster.activation_paths(&[a], &[d]).is_empty());
+    }
+
+    #[test]
+    fn test_ppr_dangling_seed_and_top_k() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (a, b) = (MemoryId::new(), MemoryId::new());
+        let cluster = build_cluster(&model, &[(a, "雨天"), (b, "散步")], vec![]);
+
+        let ranked = RetrAssociation::new(10).personalized_pagerank(&cluster, &[(a, 1.0)]);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].0, a);
+        assert!((ranked[0].1 - 1.0).abs() < 1e-2);
+
+        let unknown =
+            RetrAssociation::new(10).personalized_pagerank(&cluster, &[(MemoryId::new(), 1.0)]);
+        assert!(unknown.is_empty());
+
+        let (c, d) = (MemoryId::new(), MemoryId::new());
+        let cluster = build_cluster(
+            &model,
+            &[(c, "学校"), (d, "操场")],
+            vec![sem_link(c, d, 1.0)],
+        );
+        let ranked = RetrAssociation::new(1).personalized_pagerank(&cluster, &[(c, 1.0)]);
+        assert_eq!(ranked.len(), 1);
     }
 }
@@ -0,0 +1,300 @@
+//按优先级批量执行查询：全局的结果数与耗时预算按优先级分配给各个查询，重复命中去重后合并为一个排序列表
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::memory::embedding::Embeddable;
+use crate::memory::memory_note::MemoryId;
+use crate::memory::query::retrieve::PrioritizedMemoryRetrieveQuery;
+use crate::memory::working_memory::WorkingMemory;
+
+use super::similarity::RetrSimilarity;
+use super::{Provenance, RetrResult, RetrStrategy, RetrievalResult};
+
+pub struct RetrBatch {
+    similarity: RetrSimilarity,
+    max_results: usize,            //全部查询合并后的结果数上限
+    time_budget: Option<Duration>, //全部查询的耗时预算，None表示不限
+}
+impl RetrBatch {
+    pub fn new(similarity: RetrSimilarity, max_results: usize) -> Self {
+        Self {
+            similarity,
+            max_results,
+            time_budget: None,
+        }
+    }
+    pub fn with_time_budget(mut self, time_budget: Duration) -> Self {
+        self.time_budget = Some(time_budget);
+        self
+    }
+    pub fn max_results(&self) -> usize {
+        self.max_results
+    }
+    pub fn time_budget(&self) -> Option<Duration> {
+        self.time_budget
+    }
+
+    /// 各查询按优先级占总预算的比例，优先级全为0时均分
+    pub fn shares(queries: &[PrioritizedMemoryRetrieveQuery]) -> Vec<f32> {
+        let total = queries.iter().map(|q| q.priority() as f32).sum::<f32>();
+        queries
+            .iter()
+            .map(|q| {
+                if total > 0.0 {
+                    q.priority() as f32 / total
+                } else {
+                    1.0 / queries.len() as f32
+                }
+            })
+            .collect()
+    }
+
+    /// 按最大余数法将结果预算分配给各查询，配额之和恰为max_results，余数相同时优先级高者优先
+    pub fn quotas(&self, queries: &[PrioritizedMemoryRetrieveQuery]) -> Vec<usize> {
+        let exact = Self::shares(queries)
+            .into_iter()
+            .map(|share| share * self.max_results as f32)
+            .collect::<Vec<_>>();
+        let mut quotas = exact.iter().map(|x| x.floor() as usize).collect::<Vec<_>>();
+        let mut order = (0..queries.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            (exact[b] - exact[b].floor())
+                .total_cmp(&(exact[a] - exact[a].floor()))
+                .then_with(|| queries[b].priority().cmp(&queries[a].priority()))
+                .then_with(|| a.cmp(&b))
+        });
+        let remainder = self.max_results.saturating_sub(quotas.iter().sum());
+        order
+            .into_iter()
+            .cycle()
+            .take(if queries.is_empty() { 0 } else { remainder })
+            .for_each(|index| quotas[index] += 1);
+        quotas
+    }
+
+    /// 按优先级从高到低依次执行查询
+    ///
+    /// 每个查询分得与优先级成比例的耗时，未用完的时间顺延给后续查询，超时的部分也由后续查询承担；
+    /// 累计耗时超过截至当前查询的分配总和时，剩余查询被跳过（优先级最高的查询总会执行）。
+    /// 每个查询至多贡献配额数量的新记忆，多个查询命中同一记忆时只保留一条，
+    /// 分数取各次命中中按优先级加权（priority / 最高优先级）后的最大值。
+    pub fn execute(&self, request: BatchRequest) -> RetrResult<BatchRetrieval> {
+        let BatchRequest {
+            working_mem,
+            queries,
+        } = request;
+        let cluster = working_mem.cluster();
+        let started = Instant::now();
+
+        let shares = Self::shares(&queries);
+        let quotas = self.quotas(&queries);
+        let max_priority = queries.iter().map(|q| q.priority()).max().unwrap_or(0);
+        let weight_of = |priority: u32| {
+            if max_priority == 0 {
+                1.0
+            } else {
+                priority as f32 / max_priority as f32
+            }
+        };
+
+        let mut order = (0..queries.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            queries[b]
+                .priority()
+                .cmp(&queries[a].priority())
+                .then_with(|| a.cmp(&b))
+        });
+
+        let mut merged: HashMap<MemoryId, f32> = HashMap::new();
+        let (mut executed, mut skipped) = (Vec::new(), Vec::new());
+        let mut allotted = Duration::ZERO;
+        for index in order {
+            if let Some(budget) = self.time_budget {
+                allotted += budget.mul_f32(shares[index]);
+                if !executed.is_empty() && started.elapsed() > allotted {
+                    skipped.push(index);
+                    continue;
+                }
+            }
+            executed.push(index);
+            let quota = quotas[index];
+            if quota == 0 {
+                continue;
+            }
+
+            let query = &queries[index];
+            let weight = weight_of(query.priority());
+            let embedding = query.query().embed(self.similarity.model().as_ref())?;
+            //多取已合并的数量，保证去重后仍能贡献足额的新记忆
+            let scored = self
+                .similarity
+                .search_top(cluster, &embedding, quota + merged.len())?;
+            let mut fresh = 0;
+            for (id, score) in scored {
+                let weighted = score * weight;
+                match merged.get_mut(&id) {
+                    Some(best) => *best = best.max(weighted),
+                    None if fresh < quota => {
+                        merged.insert(id, weighted);
+                        fresh += 1;
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        let mut result = RetrievalResult::from_scored(cluster, merged, Provenance::Similarity);
+        result.truncate(self.max_results);
+        Ok(BatchRetrieval {
+            result,
+            quotas,
+            executed,
+            skipped,
+            elapsed: started.elapsed(),
+        })
+    }
+}
+
+pub struct BatchRequest {
+    working_mem: Arc<WorkingMemory>,
+    queries: Vec<PrioritizedMemoryRetrieveQuery>, //例如由LLM从一轮用户输入中分解得到的多个查询
+}
+impl BatchRequest {
+    pub fn new(
+        working_mem: Arc<WorkingMemory>,
+        queries: Vec<PrioritizedMemoryRetrieveQuery>,
+    ) -> Self {
+        Self {
+            working_mem,
+            queries,
+        }
+    }
+}
+impl RetrStrategy for RetrBatch {
+    type RetrRequest = BatchRequest;
+    fn retrieve(&self, request: Self::RetrRequest) -> RetrResult<RetrievalResult> {
+        Ok(self.execute(request)?.into_result())
+    }
+}
+
+//批量检索的结果与执行情况，查询以其在请求中的下标表示
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchRetrieval {
+    result: RetrievalResult,
+    quotas: Vec<usize>,   //各查询分得的结果配额
+    executed: Vec<usize>, //按执行顺序排列
+    skipped: Vec<usize>,  //因耗时预算耗尽而跳过的查询
+    elapsed: Duration,
+}
+impl BatchRetrieval {
+    pub fn result(&self) -> &RetrievalResult {
+        &self.result
+    }
+    pub fn quotas(&self) -> &[usize] {
+        &self.quotas
+    }
+    pub fn executed(&self) -> &[usize] {
+        &self.executed
+    }
+    pub fn skipped(&self) -> &[usize] {
+        &self.skipped
+    }
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+    pub fn into_result(self) -> RetrievalResult {
+        self.result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::algo::retrieve::fixtures::prepare_working_mem;
+    use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+    use crate::memory::query::retrieve::{
+        MemoryRetrieveQuery, MemoryRetrieveQueryVariant, SemanticQueryUnit,
+    };
+
+    fn prioritized(concept: &str, priority: u32) -> PrioritizedMemoryRetrieveQuery {
+        MemoryRetrieveQuery::new(
+            vec!["日常".to_string()],
+            MemoryRetrieveQueryVariant::make_semantic(vec![
+                SemanticQueryUnit::new().with_concept_identifier(concept.to_string()),
+            ]),
+        )
+        .with_priority(priority)
+    }
+
+    #[test]
+    fn test_batch_quotas_follow_priority() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let batch = RetrBatch::new(RetrSimilarity::new(model, 0.0, 10), 5);
+        let queries = vec![
+            prioritized("a", 1),
+            prioritized("b", 3),
+            prioritized("c", 1),
+        ];
+        //精确配额为1, 3, 1
+        assert_eq!(batch.quotas(&queries), vec![1, 3, 1]);
+
+        let queries = vec![prioritized("a", 1), prioritized("b", 2)];
+        //精确配额为1.67, 3.33，余数大的先得
+        assert_eq!(batch.quotas(&queries), vec![2, 3]);
+
+        let queries = vec![prioritized("a", 0), prioritized("b", 0)];
+        assert_eq!(batch.quotas(&queries), vec![3, 2]);
+        assert!(batch.quotas(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_batch_merges_and_deduplicates() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let working_mem =
+            prepare_working_mem(&model, &["咖啡", "咖啡机", "文玩", "麻辣烫", "火锅"]);
+        let batch = RetrBatch::new(RetrSimilarity::new(model.clone(), 0.0, 10), 4);
+
+        //两个查询高度重合，去重后仍能凑满预算
+        let retrieval = batch
+            .execute(BatchRequest::new(
+                working_mem.clone(),
+                vec![prioritized("咖啡", 1), prioritized("咖啡", 3)],
+            ))
+            .unwrap();
+        assert_eq!(retrieval.quotas(), &[1, 3]);
+        assert_eq!(retrieval.executed(), &[1, 0]);
+        let ids = retrieval.result().ids().collect::<Vec<_>>();
+        assert_eq!(ids.len(), 4);
+        assert_eq!(
+            ids.iter().collect::<std::collections::HashSet<_>>().len(),
+            4
+        );
+        let scores = retrieval
+            .result()
+            .memories()
+            .iter()
+            .map(|memory| memory.score())
+            .collect::<Vec<_>>();
+        assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    #[test]
+    fn test_batch_time_budget_skips_low_priority() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let working_mem = prepare_working_mem(&model, &["咖啡", "文玩", "火锅"]);
+        let batch = RetrBatch::new(RetrSimilarity::new(model.clone(), 0.0, 10), 3)
+            .with_time_budget(Duration::ZERO);
+
+        let retrieval = batch
+            .execute(BatchRequest::new(
+                working_mem,
+                vec![prioritized("火锅", 1), prioritized("咖啡", 2)],
+            ))
+            .unwrap();
+        //预算耗尽时只执行优先级最高的查询
+        assert_eq!(retrieval.executed(), &[1]);
+        assert_eq!(retrieval.skipped(), &[0]);
+        assert_eq!(retrieval.result().len(), retrieval.quotas()[1]);
+    }
+}
@@ -1,18 +1,441 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
+use async_trait::async_trait;
+use petgraph::Direction;
+use petgraph::visit::EdgeRef;
+use serde::Deserialize;
+
+use crate::memory::memory_cluster::MemoryCluster;
+use crate::memory::memory_links::{LinkId, MemoryLinkType};
+use crate::memory::memory_note::MemoryId;
 use crate::memory::working_memory::WorkingMemory;
+use crate::memory::working_memory::llm::client::LlmClient;
+use crate::memory::working_memory::llm::prompt::ChatPrompt;
 
-use super::RetrStrategy;
+use super::{AsyncRetrStrategy, Provenance, RetrResult, RetrievalResult, RetrievedMemory};
 // 采用 LLM进行的Plan-on-Graph
 pub struct RetrDeepThought {
+    client: Arc<LlmClient>,
     max_depth: usize,
+    beam_width: usize, //每一层最多沿多少条边继续探索
+}
+impl RetrDeepThought {
+    pub fn new(client: Arc<LlmClient>, max_depth: usize) -> Self {
+        Self {
+            client,
+            max_depth,
+            beam_width: 3,
+        }
+    }
+    pub fn with_beam_width(mut self, beam_width: usize) -> Self {
+        self.beam_width = beam_width.max(1);
+        self
+    }
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+    pub fn beam_width(&self) -> usize {
+        self.beam_width
+    }
+
+    /// 从seeds出发执行Plan-on-Graph，返回探索过的推理路径
+    ///
+    /// 每一层：列出当前前沿节点所有指向未访问节点的出边，由LLM选择沿哪些边继续，
+    /// 剪枝到beam_width条后扩展前沿，再由LLM判断已收集的子图是否足以回答问题。
+    /// LLM判断足够、前沿无可扩展的边，或达到max_depth时停止。
+    pub async fn plan(
+        &self,
+        cluster: &MemoryCluster,
+        question: &str,
+        seeds: &[MemoryId],
+    ) -> RetrResult<ReasoningPath> {
+        let mut path = ReasoningPath::default();
+        let mut visited = HashSet::new();
+        let mut frontier = Vec::new();
+        for &seed in seeds {
+            if cluster.contains_node(seed) && visited.insert(seed) {
+                frontier.push(seed);
+                path.explored.push((seed, 0));
+            }
+        }
+        if frontier.is_empty() {
+            return Ok(path);
+        }
+
+        let decomposed: Decomposition = self
+            .client
+            .call_llm_json(&mut PlanPrompt::decompose(question))
+            .await?;
+        path.sub_goals = decomposed.sub_goals;
+
+        for depth in 1..=self.max_depth {
+            let candidates = Self::candidates(cluster, &frontier, &visited);
+            if candidates.is_empty() {
+                break;
+            }
+            let selection: Selection = self
+                .client
+                .call_llm_json(&mut PlanPrompt::select(
+                    question,
+                    &path.sub_goals,
+                    cluster,
+                    &candidates,
+                ))
+                .await?;
+
+            //剪枝：忽略越界和重复的选择，同一目标节点只保留第一条边
+            let mut next_frontier = Vec::new();
+            for index in selection.links {
+                if next_frontier.len() >= self.beam_width {
+                    break;
+                }
+                let Some(step) = candidates.get(index) else {
+                    continue;
+                };
+                if !visited.insert(step.to) {
+                    continue;
+                }
+                next_frontier.push(step.to);
+                path.explored.push((step.to, depth));
+                path.steps.push(ReasoningStep { depth, ..*step });
+            }
+            if next_frontier.is_empty() {
+                break;
+            }
+            frontier = next_frontier;
+
+            let judgement: Judgement = self
+                .client
+                .call_llm_json(&mut PlanPrompt::judge(
+                    question,
+                    &path.sub_goals,
+                    cluster,
+                    &path,
+                ))
+                .await?;
+            if judgement.sufficient {
+                path.sufficient = true;
+                break;
+            }
+        }
+        Ok(path)
+    }
+
+    //前沿节点指向未访问节点的所有出边，按前沿顺序排列
+    fn candidates(
+        cluster: &MemoryCluster,
+        frontier: &[MemoryId],
+        visited: &HashSet<MemoryId>,
+    ) -> Vec<ReasoningStep> {
+        let graph = cluster.graph();
+        frontier
+            .iter()
+            .filter_map(|&from| cluster.node_index(from).map(|index| (from, index)))
+            .flat_map(move |(from, index)| {
+                graph
+                    .edges_directed(index, Direction::Outgoing)
+                    .filter_map(move |edge| {
+                        let to = graph.node_weight(edge.target())?.id();
+                        Some(ReasoningStep {
+                            depth: 0,
+                            link_id: edge.weight().id(),
+                            from,
+                            to,
+                        })
+                    })
+            })
+            .filter(|step| !visited.contains(&step.to))
+            .collect()
+    }
 }
+
 pub struct DeepThoughtRequest {
     working_mem: Arc<WorkingMemory>,
+    question: String,     //自然语言描述的问题，将由LLM拆解
+    seeds: Vec<MemoryId>, //推理的起点，通常来自相似度检索
+}
+impl DeepThoughtRequest {
+    pub fn new(working_mem: Arc<WorkingMemory>, question: String, seeds: Vec<MemoryId>) -> Self {
+        Self {
+            working_mem,
+            question,
+            seeds,
+        }
+    }
 }
-impl RetrStrategy for RetrDeepThought {
+#[async_trait]
+impl AsyncRetrStrategy for RetrDeepThought {
     type RetrRequest = DeepThoughtRequest;
-    fn retrieve(&self, request: Self::RetrRequest) -> Vec<String> {
-        todo!()
+    async fn retrieve_async(&self, request: Self::RetrRequest) -> RetrResult<RetrievalResult> {
+        let cluster = request.working_mem.cluster();
+        let path = self
+            .plan(cluster, &request.question, &request.seeds)
+            .await?;
+        Ok(path.to_result(cluster))
+    }
+}
+
+//推理路径中的一步，即沿一条边从from走到to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReasoningStep {
+    depth: usize,
+    link_id: LinkId,
+    from: MemoryId,
+    to: MemoryId,
+}
+impl ReasoningStep {
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+    pub fn link_id(&self) -> LinkId {
+        self.link_id
+    }
+    pub fn from(&self) -> MemoryId {
+        self.from
+    }
+    pub fn to(&self) -> MemoryId {
+        self.to
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReasoningPath {
+    sub_goals: Vec<String>,
+    steps: Vec<ReasoningStep>,
+    explored: Vec<(MemoryId, usize)>, //按探索顺序记录的节点及其所在层，种子为第0层
+    sufficient: bool,                 //LLM是否判断收集到的信息已经足够
+}
+impl ReasoningPath {
+    pub fn sub_goals(&self) -> &[String] {
+        &self.sub_goals
+    }
+    pub fn steps(&self) -> &[ReasoningStep] {
+        &self.steps
+    }
+    pub fn explored(&self) -> &[(MemoryId, usize)] {
+        &self.explored
+    }
+    pub fn is_sufficient(&self) -> bool {
+        self.sufficient
+    }
+    /// 转换为检索结果，第d层探索到的节点分数为1/(d+1)
+    pub fn to_result(&self, cluster: &MemoryCluster) -> RetrievalResult {
+        let memories = self
+            .explored
+            .iter()
+            .filter_map(|&(id, depth)| {
+                cluster.get_node(id).map(|note| {
+                    RetrievedMemory::new(
+                        id,
+                        1.0 / (depth + 1) as f32,
+                        note.mem_type().clone(),
+                        Provenance::DeepThought { depth },
+                    )
+                })
+            })
+            .collect();
+        RetrievalResult::new(memories)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Decomposition {
+    sub_goals: Vec<String>,
+}
+#[derive(Debug, Deserialize)]
+struct Selection {
+    links: Vec<usize>,
+}
+#[derive(Debug, Deserialize)]
+struct Judgement {
+    sufficient: bool,
+}
+
+struct PlanPrompt;
+impl PlanPrompt {
+    fn decompose(question: &str) -> ChatPrompt {
+        ChatPrompt::new(
+            "You are planning a search over a memory graph. Decompose the question into a few short sub-goals that must be answered. \
+             Reply with JSON only: {\"sub_goals\": [\"...\"]}",
+            &format!("Question: {question}"),
+        )
+    }
+    fn select(
+        question: &str,
+        sub_goals: &[String],
+        cluster: &MemoryCluster,
+        candidates: &[ReasoningStep],
+    ) -> ChatPrompt {
+        let candidates = candidates
+            .iter()
+            .enumerate()
+            .map(|(index, step)| format!("[{index}] {}", Self::describe_step(cluster, step)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        ChatPrompt::new(
+            "You are exploring a memory graph to answer a question. Choose the links worth following next, most relevant first. \
+             Reply with JSON only: {\"links\": [index, ...]}; reply {\"links\": []} if none is relevant.",
+            &format!(
+                "Question: {question}\nSub-goals: {}\nCandidate links:\n{candidates}",
+                sub_goals.join("; ")
+            ),
+        )
+    }
+    fn judge(
+        question: &str,
+        sub_goals: &[String],
+        cluster: &MemoryCluster,
+        path: &ReasoningPath,
+    ) -> ChatPrompt {
+        let gathered = path
+            .explored
+            .iter()
+            .map(|&(id, _)| format!("- {}", Self::describe_memory(cluster, id)))
+            .chain(
+                path.steps
+                    .iter()
+                    .map(|step| format!("- {}", Self::describe_step(cluster, step))),
+            )
+            .collect::<Vec<_>>()
+            .join("\n");
+        ChatPrompt::new(
+            "You are exploring a memory graph to answer a question. Judge whether the gathered memories are sufficient to answer it. \
+             Reply with JSON only: {\"sufficient\": true} or {\"sufficient\": false}",
+            &format!(
+                "Question: {question}\nSub-goals: {}\nGathered memories:\n{gathered}",
+                sub_goals.join("; ")
+            ),
+        )
+    }
+    fn describe_memory(cluster: &MemoryCluster, id: MemoryId) -> String {
+        cluster
+            .get_node(id)
+            .and_then(|note| serde_json::to_string(note.mem_type()).ok())
+            .unwrap_or_default()
+    }
+    fn describe_link(link_type: &MemoryLinkType) -> String {
+        serde_json::to_string(link_type).unwrap_or_default()
+    }
+    fn describe_step(cluster: &MemoryCluster, step: &ReasoningStep) -> String {
+        let link = cluster
+            .get_edge(step.link_id)
+            .map(|edge| Self::describe_link(edge.link_type()))
+            .unwrap_or_default();
+        format!(
+            "{} --{link}--> {}",
+            Self::describe_memory(cluster, step.from),
+            Self::describe_memory(cluster, step.to)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::embedding::Embeddable;
+    use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+    use crate::memory::memory_links::MemoryLink;
+    use crate::memory::memory_links::sem_mem::SemMemLink;
+    use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+    use crate::memory::memory_note::{MemoryNoteBuilder, MemoryType};
+    use crate::memory::working_memory::llm::mock::MockLlmServer;
+    use crate::memory::working_memory::sliding_window::SlidingWindow;
+
+    //构造一条 a -> b -> c -> d 的链
+    fn chain_working_mem(ids: &[MemoryId; 4]) -> Arc<WorkingMemory> {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let contents = ["朋友", "咖啡", "咖啡机", "礼物"];
+        let notes = ids
+            .iter()
+            .zip(contents)
+            .enumerate()
+            .map(|(i, (&id, content))| {
+                let links = ids
+                    .get(i + 1)
+                    .map(|&next| {
+                        vec![MemoryLink::new(
+                            id,
+                            next,
+                            MemoryLinkType::Sem(SemMemLink::new("相关".to_string(), 1.0, 1.0)),
+                        )]
+                    })
+                    .unwrap_or_default();
+                MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+                    content.to_string(),
+                    ConceptType::Entity,
+                    format!("{content}的描述"),
+                )))
+                .id(id)
+                .mem_links(links)
+                .build()
+                .unwrap()
+                .embed_and_fuse(&model)
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let mut cluster = MemoryCluster::new();
+        cluster.merge(notes);
+        Arc::new(WorkingMemory::new(cluster, SlidingWindow::new(4)))
+    }
+
+    #[tokio::test]
+    async fn test_deep_thought_stops_when_sufficient() {
+        let ids = [MemoryId::new(), MemoryId::new(), MemoryId::new(), MemoryId::new()];
+        let working_mem = chain_working_mem(&ids);
+        let server = MockLlmServer::start(vec![
+            r#"{"sub_goals": ["朋友喜欢什么", "适合送什么"]}"#.to_string(),
+            r#"{"links": [0]}"#.to_string(),
+            r#"{"sufficient": false}"#.to_string(),
+            "```json\n{\"links\": [0, 5]}\n```".to_string(),
+            r#"{"sufficient": true}"#.to_string(),
+        ])
+        .await;
+
+        let retr = RetrDeepThought::new(Arc::new(server.client()), 5);
+        let path = retr
+            .plan(working_mem.cluster(), "给朋友送什么礼物？", &[ids[0]])
+            .await
+            .unwrap();
+        assert!(path.is_sufficient());
+        assert_eq!(path.sub_goals().len(), 2);
+        assert_eq!(
+            path.steps()
+                .iter()
+                .map(|step| (step.from(), step.to(), step.depth()))
+                .collect::<Vec<_>>(),
+            vec![(ids[0], ids[1], 1), (ids[1], ids[2], 2)]
+        );
+        assert_eq!(server.requests().len(), 5);
+
+        let result = path.to_result(working_mem.cluster());
+        assert_eq!(result.ids().collect::<Vec<_>>(), vec![ids[0], ids[1], ids[2]]);
+        assert_eq!(
+            result.memories()[2].provenance(),
+            &Provenance::DeepThought { depth: 2 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deep_thought_respects_max_depth() {
+        let ids = [MemoryId::new(), MemoryId::new(), MemoryId::new(), MemoryId::new()];
+        let working_mem = chain_working_mem(&ids);
+        let server = MockLlmServer::start(vec![
+            r#"{"sub_goals": ["朋友喜欢什么"]}"#.to_string(),
+            r#"{"links": [0]}"#.to_string(),
+            r#"{"sufficient": false}"#.to_string(),
+        ])
+        .await;
+
+        let result = RetrDeepThought::new(Arc::new(server.client()), 1)
+            .retrieve_async(DeepThoughtRequest::new(
+                working_mem,
+                "给朋友送什么礼物？".to_string(),
+                vec![ids[0]],
+            ))
+            .await
+            .unwrap();
+        assert_eq!(result.ids().collect::<Vec<_>>(), vec![ids[0], ids[1]]);
+        assert_eq!(server.requests().len(), 3);
     }
 }
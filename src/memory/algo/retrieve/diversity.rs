@@ -0,0 +1,236 @@
+//多样性选择：用最大边际相关（MMR）从检索结果中挑选彼此不重复的记忆，避免近似重复的情境占满上下文
+use std::collections::HashMap;
+
+use crate::memory::embedding::{EmbeddingCalcResult, EmbeddingVec};
+use crate::memory::memory_cluster::MemoryCluster;
+use crate::memory::memory_note::{MemoryId, MemoryKind};
+
+use super::RetrievalResult;
+
+pub struct MmrSelector {
+    max_results: usize,
+    relevance_weight: f32, //λ，1为只看相关度（等价于top-k），0为只看多样性
+    quotas: HashMap<MemoryKind, usize>, //各记忆类型最多入选的数量，未设置的类型不限
+}
+impl MmrSelector {
+    pub fn new(max_results: usize) -> Self {
+        Self {
+            max_results,
+            relevance_weight: 0.7,
+            quotas: HashMap::new(),
+        }
+    }
+    pub fn with_relevance_weight(mut self, relevance_weight: f32) -> Self {
+        self.relevance_weight = relevance_weight.clamp(0.0, 1.0);
+        self
+    }
+    pub fn with_quota(mut self, kind: MemoryKind, quota: usize) -> Self {
+        self.quotas.insert(kind, quota);
+        self
+    }
+    pub fn max_results(&self) -> usize {
+        self.max_results
+    }
+    pub fn relevance_weight(&self) -> f32 {
+        self.relevance_weight
+    }
+    pub fn quota(&self, kind: MemoryKind) -> Option<usize> {
+        self.quotas.get(&kind).copied()
+    }
+
+    /// 贪心地选出max_results条记忆，每一步选择 λ·分数 - (1-λ)·与已选记忆的最大相似度 最高者
+    ///
+    /// 记忆之间的相似度为各自代表向量（MemoryEmbedding::primary_vector）的余弦相似度，
+    /// 不在cluster中或没有嵌入的记忆视为与其他记忆不相似。返回的结果仍按分数降序排列。
+    pub fn select(
+        &self,
+        cluster: &MemoryCluster,
+        mut result: RetrievalResult,
+    ) -> EmbeddingCalcResult<RetrievalResult> {
+        let candidates = result
+            .memories()
+            .iter()
+            .map(|memory| {
+                let vector = cluster
+                    .get_embedding(memory.id())
+                    .map(|embedding| Self::unit(embedding.primary_vector()))
+                    .transpose()?;
+                Ok((
+                    memory.id(),
+                    memory.score(),
+                    MemoryKind::of(memory.mem_type()),
+                    vector,
+                ))
+            })
+            .collect::<EmbeddingCalcResult<Vec<_>>>()?;
+
+        let mut remaining = (0..candidates.len()).collect::<Vec<_>>();
+        let mut redundancy = vec![f32::NEG_INFINITY; candidates.len()]; //与已选记忆的最大相似度
+        let mut taken: HashMap<MemoryKind, usize> = HashMap::new();
+        let mut selected = Vec::new();
+        while selected.len() < self.max_results {
+            remaining.retain(|&i| {
+                let kind = candidates[i].2;
+                self.quota(kind)
+                    .is_none_or(|quota| taken.get(&kind).copied().unwrap_or(0) < quota)
+            });
+            //remaining保持原有的分数降序，同分时先出现者优先
+            let Some((position, &best)) = remaining.iter().enumerate().max_by(|x, y| {
+                let (a, b) = (*x.1, *y.1);
+                self.marginal(candidates[a].1, redundancy[a])
+                    .total_cmp(&self.marginal(candidates[b].1, redundancy[b]))
+                    .then_with(|| b.cmp(&a))
+            }) else {
+                break;
+            };
+            remaining.remove(position);
+            *taken.entry(candidates[best].2).or_default() += 1;
+            selected.push(candidates[best].0);
+
+            if let Some(chosen) = &candidates[best].3 {
+                for &i in &remaining {
+                    if let Some(vector) = &candidates[i].3 {
+                        redundancy[i] = redundancy[i].max(vector.dot(chosen)?);
+                    }
+                }
+            }
+        }
+
+        result.retain(|memory| selected.contains(&memory.id()));
+        Ok(result)
+    }
+
+    fn marginal(&self, relevance: f32, redundancy: f32) -> f32 {
+        //尚未选出任何相似记忆时不扣分
+        let redundancy = if redundancy.is_finite() {
+            redundancy
+        } else {
+            0.0
+        };
+        self.relevance_weight * relevance - (1.0 - self.relevance_weight) * redundancy
+    }
+
+    fn unit(vector: &EmbeddingVec) -> EmbeddingCalcResult<EmbeddingVec> {
+        let norm = vector.norm()?;
+        if norm > 0.0 {
+            vector.normalize()
+        } else {
+            Ok(vector.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+
+    use super::*;
+    use crate::memory::algo::retrieve::{Provenance, RetrievedMemory};
+    use crate::memory::embedding::Embeddable;
+    use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+    use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+    use crate::memory::memory_note::situation_mem::{
+        Context, Environment, SituationType, SpecificSituation,
+    };
+    use crate::memory::memory_note::{MemoryNoteBuilder, MemoryType};
+
+    fn situation(narrative: &str, days_ago: i64) -> MemoryType {
+        MemoryType::Situation(SituationType::SpecificSituation(SpecificSituation::new(
+            narrative.to_string(),
+            Utc::now() - TimeDelta::days(days_ago),
+            Context::new(
+                None,
+                vec![],
+                vec![],
+                vec![],
+                Environment {
+                    atmosphere: "轻松".to_string(),
+                    tone: "愉快".to_string(),
+                },
+                vec![],
+            ),
+        )))
+    }
+
+    //三条几乎相同的咖啡约会、一条散步、一条语义记忆，分数依次递减
+    fn prepare(model: &BgeSmallZh) -> (MemoryCluster, RetrievalResult, Vec<MemoryId>) {
+        let mem_types = vec![
+            situation("和小林在咖啡馆喝咖啡约会", 1),
+            situation("和小林在咖啡馆喝咖啡约会", 8),
+            situation("和小林在咖啡店喝咖啡约会", 15),
+            situation("和小林在河边散步看日落", 3),
+            MemoryType::Semantic(SemMemory::new(
+                "拿铁".to_string(),
+                ConceptType::Entity,
+                "加了牛奶的咖啡".to_string(),
+            )),
+        ];
+        let mut cluster = MemoryCluster::new();
+        let mut memories = Vec::new();
+        let mut ids = Vec::new();
+        for (i, mem_type) in mem_types.into_iter().enumerate() {
+            let note = MemoryNoteBuilder::new(mem_type.clone())
+                .tags(vec!["小林".to_string()])
+                .build()
+                .unwrap()
+                .embed_and_fuse(model)
+                .unwrap();
+            let id = note.note().id();
+            cluster.add_single_node(note).unwrap();
+            memories.push(RetrievedMemory::new(
+                id,
+                0.9 - 0.1 * i as f32,
+                mem_type,
+                Provenance::Similarity,
+            ));
+            ids.push(id);
+        }
+        (cluster, RetrievalResult::new(memories), ids)
+    }
+
+    #[test]
+    fn test_mmr_skips_near_duplicates() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (cluster, result, ids) = prepare(&model);
+
+        //λ为1时等价于按分数取top-k
+        let top = MmrSelector::new(3)
+            .with_relevance_weight(1.0)
+            .select(&cluster, result.clone())
+            .unwrap();
+        assert_eq!(top.ids().collect::<Vec<_>>(), ids[..3].to_vec());
+
+        let diverse = MmrSelector::new(3)
+            .with_relevance_weight(0.3)
+            .select(&cluster, result.clone())
+            .unwrap();
+        let selected = diverse.ids().collect::<Vec<_>>();
+        assert_eq!(selected.len(), 3);
+        assert_eq!(selected[0], ids[0]);
+        assert!(!selected.contains(&ids[1]));
+        assert!(selected.contains(&ids[3]));
+    }
+
+    #[test]
+    fn test_mmr_type_quotas() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (cluster, result, ids) = prepare(&model);
+
+        let selected = MmrSelector::new(3)
+            .with_relevance_weight(1.0)
+            .with_quota(MemoryKind::Situation, 1)
+            .select(&cluster, result.clone())
+            .unwrap()
+            .ids()
+            .collect::<Vec<_>>();
+        //情境记忆只能入选一条，剩余名额不足时结果少于max_results
+        assert_eq!(selected, vec![ids[0], ids[4]]);
+
+        let none = MmrSelector::new(3)
+            .with_quota(MemoryKind::Situation, 0)
+            .with_quota(MemoryKind::Semantic, 0)
+            .select(&cluster, result)
+            .unwrap();
+        assert!(none.is_empty());
+    }
+}
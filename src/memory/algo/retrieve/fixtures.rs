@@ -0,0 +1,29 @@
+//各检索策略测试共用的记忆簇构造函数
+use std::sync::Arc;
+
+use crate::memory::embedding::Embeddable;
+use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+use crate::memory::memory_cluster::MemoryCluster;
+use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+use crate::memory::memory_note::{MemoryNoteBuilder, MemoryType};
+use crate::memory::working_memory::WorkingMemory;
+use crate::memory::working_memory::sliding_window::SlidingWindow;
+
+/// 带“日常”标签、没有连接的实体概念组成的工作记忆
+pub(crate) fn prepare_working_mem(model: &BgeSmallZh, contents: &[&str]) -> Arc<WorkingMemory> {
+    let mut cluster = MemoryCluster::new();
+    for content in contents {
+        let note = MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+            content.to_string(),
+            ConceptType::Entity,
+            format!("关于{content}的记忆"),
+        )))
+        .tags(vec!["日常".to_string()])
+        .build()
+        .unwrap()
+        .embed_and_fuse(model)
+        .unwrap();
+        cluster.add_single_node(note).unwrap();
+    }
+    Arc::new(WorkingMemory::new(cluster, SlidingWindow::new(4)))
+}
@@ -0,0 +1,509 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::memory::memory_cluster::MemorySubCluster;
+use crate::memory::memory_note::MemoryId;
+use crate::memory::query::retrieve::MemoryRetrieveQuery;
+use crate::memory::working_memory::WorkingMemory;
+use crate::memory::working_memory::llm::client::LlmClient;
+use crate::memory::working_memory::llm::prompt::ChatPrompt;
+
+use super::association::{AssociationRequest, RetrAssociation};
+use super::deep_thought::RetrDeepThought;
+use super::similarity::{RetrSimilarity, SimilarityRequest};
+use super::{AsyncRetrStrategy, RetrError, RetrResult, RetrStrategy, RetrievalResult};
+
+//分层混合检索（Soul-Retr）：先相似度top-k，不足时升级为带权PPR联想，仍不足时最后使用Plan-on-Graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetrievalTier {
+    Similarity,
+    Association,
+    DeepThought,
+}
+
+/// 判断当前检索结果是否足以回答问题，决定是否升级到下一层检索
+#[async_trait]
+pub trait SufficiencyJudge {
+    async fn is_sufficient(
+        &self,
+        question: &str,
+        subgraph: &MemorySubCluster<'_>,
+        result: &RetrievalResult,
+    ) -> RetrResult<bool>;
+}
+
+//基于规则的判断：至少有min_results条分数不低于min_score的记忆
+pub struct ThresholdJudge {
+    min_results: usize,
+    min_score: f32,
+}
+impl ThresholdJudge {
+    pub fn new(min_results: usize, min_score: f32) -> Self {
+        Self {
+            min_results,
+            min_score,
+        }
+    }
+}
+#[async_trait]
+impl SufficiencyJudge for ThresholdJudge {
+    async fn is_sufficient(
+        &self,
+        _question: &str,
+        _subgraph: &MemorySubCluster<'_>,
+        result: &RetrievalResult,
+    ) -> RetrResult<bool> {
+        let passed = result
+            .memories()
+            .iter()
+            .filter(|memory| memory.score() >= self.min_score)
+            .count();
+        Ok(passed >= self.min_results)
+    }
+}
+
+//由LLM判断检索到的记忆是否足以回答问题
+pub struct LlmJudge {
+    client: Arc<LlmClient>,
+}
+impl LlmJudge {
+    pub fn new(client: Arc<LlmClient>) -> Self {
+        Self { client }
+    }
+}
+#[derive(Debug, Deserialize)]
+struct Verdict {
+    sufficient: bool,
+}
+#[async_trait]
+impl SufficiencyJudge for LlmJudge {
+    async fn is_sufficient(
+        &self,
+        question: &str,
+        subgraph: &MemorySubCluster<'_>,
+        result: &RetrievalResult,
+    ) -> RetrResult<bool> {
+        let cluster = subgraph.super_cluster();
+        let memories = result
+            .memories()
+            .iter()
+            .filter_map(|memory| {
+                let note = cluster.get_node(memory.id())?;
+                let content = serde_json::to_string(note.mem_type()).ok()?;
+                Some(format!("- {content} (score {:.3})", memory.score()))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let verdict: Verdict = self
+            .client
+            .call_llm_json(&mut ChatPrompt::new(
+                "You are checking whether retrieved memories are enough to answer a question. \
+                 Reply with JSON only: {\"sufficient\": true} or {\"sufficient\": false}",
+                &format!("Question: {question}\nRetrieved memories:\n{memories}"),
+            ))
+            .await?;
+        Ok(verdict.sufficient)
+    }
+}
+
+pub struct RetrHybrid {
+    similarity: RetrSimilarity,
+    association: Option<RetrAssociation>,
+    deep_thought: Option<RetrDeepThought>,
+    judge: Arc<dyn SufficiencyJudge + Send + Sync>,
+    association_weight: f32,  //PPR结果归一化后的最高分
+    deep_thought_weight: f32, //Plan-on-Graph结果归一化后的最高分
+}
+impl RetrHybrid {
+    pub fn new(similarity: RetrSimilarity, judge: Arc<dyn SufficiencyJudge + Send + Sync>) -> Self {
+        Self {
+            similarity,
+            association: None,
+            deep_thought: None,
+            judge,
+            association_weight: 0.8,
+            deep_thought_weight: 0.6,
+        }
+    }
+    pub fn with_association(mut self, association: RetrAssociation) -> Self {
+        self.association = Some(association);
+        self
+    }
+    pub fn with_deep_thought(mut self, deep_thought: RetrDeepThought) -> Self {
+        self.deep_thought = Some(deep_thought);
+        self
+    }
+    pub fn with_association_weight(mut self, weight: f32) -> Self {
+        self.association_weight = weight;
+        self
+    }
+    pub fn with_deep_thought_weight(mut self, weight: f32) -> Self {
+        self.deep_thought_weight = weight;
+        self
+    }
+
+    /// 逐层检索，只有存在下一层时才调用判断器
+    ///
+    /// 每一层的结果都并入共享的查询子图与累积结果（同一记忆取最高分）；
+    /// 联想以子图中的全部节点及其累积分数为种子，Plan-on-Graph从子图中的全部节点出发。
+    /// 相似度分数已校准到[0, 1]，而PPR分数是总和为1的概率质量、Plan-on-Graph分数为1/(depth+1)，
+    /// 因此这两层的结果并入前按各自最高分等比缩放到对应的层权重。
+    pub async fn retrieve_tiered(&self, request: HybridRequest) -> RetrResult<TieredRetrieval> {
+        let cluster = request.working_mem.cluster();
+        let mut subgraph = cluster.sub_cluster(HashSet::new(), HashSet::new());
+        let mut tiers = vec![RetrievalTier::Similarity];
+        let mut judge_calls = 0;
+
+        let mut result = self.similarity.retrieve(SimilarityRequest::new(
+            request.working_mem.clone(),
+            request.query,
+        ))?;
+        Self::absorb(&mut subgraph, &result)?;
+
+        if let Some(association) = &self.association {
+            judge_calls += 1;
+            if self
+                .judge
+                .is_sufficient(&request.question, &subgraph, &result)
+                .await?
+            {
+                return Ok(TieredRetrieval::new(result, tiers, judge_calls, &subgraph));
+            }
+            let associated = association.retrieve(AssociationRequest::new(
+                request.working_mem.clone(),
+                Self::seeds(&subgraph, &result),
+            ))?;
+            tiers.push(RetrievalTier::Association);
+            Self::absorb(&mut subgraph, &associated)?;
+            Self::merge_tier(&mut result, associated, self.association_weight);
+        }
+
+        if let Some(deep_thought) = &self.deep_thought {
+            judge_calls += 1;
+            if self
+                .judge
+                .is_sufficient(&request.question, &subgraph, &result)
+                .await?
+            {
+                return Ok(TieredRetrieval::new(result, tiers, judge_calls, &subgraph));
+            }
+            let seeds = Self::seeds(&subgraph, &result)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>();
+            let planned = deep_thought
+                .plan(cluster, &request.question, &seeds)
+                .await?
+                .to_result(cluster);
+            tiers.push(RetrievalTier::DeepThought);
+            Self::absorb(&mut subgraph, &planned)?;
+            Self::merge_tier(&mut result, planned, self.deep_thought_weight);
+        }
+
+        Ok(TieredRetrieval::new(result, tiers, judge_calls, &subgraph))
+    }
+
+    //子图中的节点及其在累积结果中的分数，按分数降序（同分按MemoryId）排列
+    fn seeds(subgraph: &MemorySubCluster<'_>, result: &RetrievalResult) -> Vec<(MemoryId, f32)> {
+        let mut seeds = subgraph
+            .node_ids()
+            .iter()
+            .map(|&id| {
+                let score = result
+                    .memories()
+                    .iter()
+                    .find(|memory| memory.id() == id)
+                    .map_or(0.0, |memory| memory.score());
+                (id, score)
+            })
+            .collect::<Vec<_>>();
+        seeds.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        seeds
+    }
+
+    fn merge_tier(result: &mut RetrievalResult, mut tier: RetrievalResult, weight: f32) {
+        tier.rescale(weight);
+        result.merge(tier);
+    }
+
+    //结果中的节点不在子图所属的记忆簇中时返回错误，避免子图悄悄丢失节点
+    fn absorb(subgraph: &mut MemorySubCluster<'_>, result: &RetrievalResult) -> RetrResult<()> {
+        subgraph
+            .add_nodes(&result.ids().collect::<Vec<_>>())
+            .map_err(RetrError::Subgraph)
+    }
+}
+
+pub struct HybridRequest {
+    working_mem: Arc<WorkingMemory>,
+    query: MemoryRetrieveQuery, //用于相似度检索的结构化查询
+    question: String,           //自然语言问题，用于充分性判断和Plan-on-Graph
+}
+impl HybridRequest {
+    pub fn new(working_mem: Arc<WorkingMemory>, query: MemoryRetrieveQuery, question: String) -> Self {
+        Self {
+            working_mem,
+            query,
+            question,
+        }
+    }
+}
+#[async_trait]
+impl AsyncRetrStrategy for RetrHybrid {
+    type RetrRequest = HybridRequest;
+    async fn retrieve_async(&self, request: Self::RetrRequest) -> RetrResult<RetrievalResult> {
+        Ok(self.retrieve_tiered(request).await?.into_result())
+    }
+}
+
+//分层检索的结果，记录最终给出答案的层级和成本信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct TieredRetrieval {
+    result: RetrievalResult,
+    tiers: Vec<RetrievalTier>, //按顺序执行过的层级，最后一个即给出答案的层级
+    judge_calls: usize,
+    subgraph_nodes: HashSet<MemoryId>, //各层累积的查询子图
+}
+impl TieredRetrieval {
+    fn new(
+        result: RetrievalResult,
+        tiers: Vec<RetrievalTier>,
+        judge_calls: usize,
+        subgraph: &MemorySubCluster<'_>,
+    ) -> Self {
+        Self {
+            result,
+            tiers,
+            judge_calls,
+            subgraph_nodes: subgraph.node_ids().clone(),
+        }
+    }
+    pub fn tier(&self) -> RetrievalTier {
+        //SAFEUNWRAP: tiers总是至少包含Similarity
+        *self.tiers.last().unwrap()
+    }
+    pub fn tiers(&self) -> &[RetrievalTier] {
+        &self.tiers
+    }
+    pub fn judge_calls(&self) -> usize {
+        self.judge_calls
+    }
+    pub fn subgraph_nodes(&self) -> &HashSet<MemoryId> {
+        &self.subgraph_nodes
+    }
+    pub fn result(&self) -> &RetrievalResult {
+        &self.result
+    }
+    pub fn into_result(self) -> RetrievalResult {
+        self.result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::algo::retrieve::{Provenance, RetrievedMemory};
+    use crate::memory::embedding::Embeddable;
+    use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+    use crate::memory::memory_cluster::MemoryCluster;
+    use crate::memory::memory_links::sem_mem::SemMemLink;
+    use crate::memory::memory_links::{MemoryLink, MemoryLinkType};
+    use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+    use crate::memory::memory_note::{MemoryNoteBuilder, MemoryType};
+    use crate::memory::query::retrieve::{MemoryRetrieveQueryVariant, SemanticQueryUnit};
+    use crate::memory::working_memory::llm::mock::MockLlmServer;
+    use crate::memory::working_memory::sliding_window::SlidingWindow;
+
+    //咖啡 -> 咖啡机 -> 礼物
+    fn prepare(model: &BgeSmallZh) -> (Arc<WorkingMemory>, [MemoryId; 3]) {
+        let ids = [MemoryId::new(), MemoryId::new(), MemoryId::new()];
+        let notes = ids
+            .iter()
+            .zip(["咖啡", "咖啡机", "礼物"])
+            .enumerate()
+            .map(|(i, (&id, content))| {
+                let links = ids
+                    .get(i + 1)
+                    .map(|&next| {
+                        vec![MemoryLink::new(
+                            id,
+                            next,
+                            MemoryLinkType::Sem(SemMemLink::new("相关".to_string(), 1.0, 1.0)),
+                        )]
+                    })
+                    .unwrap_or_default();
+                MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+                    content.to_string(),
+                    ConceptType::Entity,
+                    format!("{content}的描述"),
+                )))
+                .id(id)
+                .tags(vec!["日常".to_string()])
+                .mem_links(links)
+                .build()
+                .unwrap()
+                .embed_and_fuse(model)
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let mut cluster = MemoryCluster::new();
+        cluster.merge(notes);
+        (
+            Arc::new(WorkingMemory::new(cluster, SlidingWindow::new(4))),
+            ids,
+        )
+    }
+
+    fn request(working_mem: Arc<WorkingMemory>) -> HybridRequest {
+        HybridRequest::new(
+            working_mem,
+            MemoryRetrieveQuery::new(
+                vec!["日常".to_string()],
+                MemoryRetrieveQueryVariant::make_semantic(vec![
+                    SemanticQueryUnit::new().with_concept_identifier("咖啡".to_string()),
+                ]),
+            ),
+            "朋友喜欢什么？".to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_stops_at_similarity() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let (working_mem, _) = prepare(&model);
+
+        let hybrid = RetrHybrid::new(
+            RetrSimilarity::new(model.clone(), 0.0, 1),
+            Arc::new(ThresholdJudge::new(1, 0.0)),
+        )
+        .with_association(RetrAssociation::new(10));
+        let outcome = hybrid.retrieve_tiered(request(working_mem)).await.unwrap();
+        assert_eq!(outcome.tier(), RetrievalTier::Similarity);
+        assert_eq!(outcome.judge_calls(), 1);
+        assert_eq!(outcome.result().len(), 1);
+
+        //没有可升级的层级时不调用判断器
+        let (working_mem, _) = prepare(&model);
+        let hybrid = RetrHybrid::new(
+            RetrSimilarity::new(model.clone(), 0.0, 1),
+            Arc::new(ThresholdJudge::new(100, 0.0)),
+        );
+        let outcome = hybrid.retrieve_tiered(request(working_mem)).await.unwrap();
+        assert_eq!(outcome.tier(), RetrievalTier::Similarity);
+        assert_eq!(outcome.judge_calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_escalates_to_association() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let (working_mem, ids) = prepare(&model);
+
+        let hybrid = RetrHybrid::new(
+            RetrSimilarity::new(model.clone(), 0.0, 1),
+            Arc::new(ThresholdJudge::new(100, 0.0)),
+        )
+        .with_association(RetrAssociation::new(10));
+        let outcome = hybrid.retrieve_tiered(request(working_mem)).await.unwrap();
+        assert_eq!(
+            outcome.tiers(),
+            &[RetrievalTier::Similarity, RetrievalTier::Association]
+        );
+        assert!(ids.iter().all(|id| outcome.subgraph_nodes().contains(id)));
+        //相似度命中的记忆仍保留在累积结果中
+        assert_eq!(
+            outcome.result().ids().collect::<HashSet<_>>(),
+            outcome.subgraph_nodes().clone()
+        );
+        assert!(outcome.result().ids().any(|id| id == ids[0]));
+    }
+
+    #[test]
+    fn test_hybrid_merges_tiers_on_a_common_scale() {
+        let [a, b, c, d, e] = [(); 5].map(|_| MemoryId::new());
+        let mem_type = MemoryType::Semantic(SemMemory::new(
+            "咖啡".to_string(),
+            ConceptType::Entity,
+            "咖啡的描述".to_string(),
+        ));
+        let tier = |scored: &[(MemoryId, f32)], provenance: Provenance| {
+            RetrievalResult::new(
+                scored
+                    .iter()
+                    .map(|&(id, score)| {
+                        RetrievedMemory::new(id, score, mem_type.clone(), provenance.clone())
+                    })
+                    .collect(),
+            )
+        };
+        let mut result = tier(&[(a, 0.9), (b, 0.5)], Provenance::Similarity);
+        //PPR分数很小，种子a的概率质量最大
+        RetrHybrid::merge_tier(
+            &mut result,
+            tier(&[(a, 0.04), (c, 0.02), (d, 0.01)], Provenance::Association),
+            0.8,
+        );
+        //Plan-on-Graph的种子分数总是1.0
+        RetrHybrid::merge_tier(
+            &mut result,
+            tier(&[(a, 1.0), (e, 0.5)], Provenance::DeepThought { depth: 1 }),
+            0.6,
+        );
+
+        //联想到的记忆排在相似度命中之间，种子不会因为Plan-on-Graph的分数排到最前
+        assert_eq!(result.ids().collect::<Vec<_>>(), vec![a, b, c, e, d]);
+        assert_eq!(result.memories()[0].provenance(), &Provenance::Similarity);
+        assert!((result.memories()[0].score() - 0.9).abs() < 1e-6);
+        assert!((result.memories()[2].score() - 0.4).abs() < 1e-6);
+        assert!((result.memories()[3].score() - 0.3).abs() < 1e-6);
+        assert!((result.memories()[4].score() - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_hybrid_absorb_reports_missing_nodes() {
+        let cluster = MemoryCluster::new();
+        let mut subgraph = cluster.sub_cluster(HashSet::new(), HashSet::new());
+        let stray = RetrievalResult::new(vec![RetrievedMemory::new(
+            MemoryId::new(),
+            1.0,
+            MemoryType::Semantic(SemMemory::new(
+                "咖啡".to_string(),
+                ConceptType::Entity,
+                "咖啡的描述".to_string(),
+            )),
+            Provenance::Similarity,
+        )]);
+        assert!(matches!(
+            RetrHybrid::absorb(&mut subgraph, &stray),
+            Err(RetrError::Subgraph(errors)) if errors.len() == 1
+        ));
+        assert!(subgraph.node_ids().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_llm_judge() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let (working_mem, _) = prepare(&model);
+        let judge_server = MockLlmServer::start(vec![
+            r#"{"sufficient": false}"#.to_string(),
+            r#"{"sufficient": true}"#.to_string(),
+        ])
+        .await;
+        //判断足够后不会调用Plan-on-Graph
+        let deep_server = MockLlmServer::start(vec![]).await;
+
+        let hybrid = RetrHybrid::new(
+            RetrSimilarity::new(model.clone(), 0.0, 1),
+            Arc::new(LlmJudge::new(Arc::new(judge_server.client()))),
+        )
+        .with_association(RetrAssociation::new(10))
+        .with_deep_thought(RetrDeepThought::new(Arc::new(deep_server.client()), 3));
+        let outcome = hybrid.retrieve_tiered(request(working_mem)).await.unwrap();
+        assert_eq!(outcome.tier(), RetrievalTier::Association);
+        assert_eq!(outcome.judge_calls(), 2);
+        assert_eq!(judge_server.requests().len(), 2);
+        assert!(deep_server.requests().is_empty());
+    }
+}
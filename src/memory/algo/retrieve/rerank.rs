@@ -0,0 +1,247 @@
+//LLM重排：把候选记忆和当前对话一并交给LLM，按其给出的相关性顺序重新排列检索结果
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use async_openai::types::chat::ChatCompletionRequestMessage;
+use serde::Deserialize;
+
+use crate::memory::working_memory::llm::client::{LlmClient, extract_json};
+use crate::memory::working_memory::llm::prompt::{ChatPrompt, PromptBuilder};
+
+use super::{RetrResult, RetrievalResult, RetrievedMemory};
+
+pub struct LlmReranker {
+    client: Arc<LlmClient>,
+    max_candidates: usize, //最多交给LLM重排的候选数，其余候选保持原顺序排在后面
+    max_prompt_tokens: usize, //候选描述部分的token预算，按字符数估计
+}
+impl LlmReranker {
+    pub fn new(client: Arc<LlmClient>) -> Self {
+        Self {
+            client,
+            max_candidates: 20,
+            max_prompt_tokens: 2000,
+        }
+    }
+    pub fn with_max_candidates(mut self, max_candidates: usize) -> Self {
+        self.max_candidates = max_candidates;
+        self
+    }
+    pub fn with_max_prompt_tokens(mut self, max_prompt_tokens: usize) -> Self {
+        self.max_prompt_tokens = max_prompt_tokens;
+        self
+    }
+    pub fn max_candidates(&self) -> usize {
+        self.max_candidates
+    }
+    pub fn max_prompt_tokens(&self) -> usize {
+        self.max_prompt_tokens
+    }
+
+    /// 按LLM给出的顺序重排result，返回的结果仍按分数降序排列
+    ///
+    /// 按分数从高到低取至多max_candidates条、描述总长不超过max_prompt_tokens的候选交给LLM，
+    /// 重排后第i名沿用原结果中第i高的分数，未被LLM提及的候选按原顺序排在已排序的候选之后。
+    /// LLM的回复无法解析时保持原顺序；请求本身失败时返回错误。
+    pub async fn rerank(&self, turn: &str, result: RetrievalResult) -> RetrResult<RetrievalResult> {
+        let candidates = self.candidates(&result);
+        if candidates.len() < 2 {
+            return Ok(result);
+        }
+        let mut prompt = RerankPrompt::new(turn, &candidates);
+        let replies = self.client.call_llm(&mut prompt).await?;
+        let Some(ranking) = replies
+            .first()
+            .and_then(|reply| serde_json::from_str::<Ranking>(extract_json(reply)).ok())
+        else {
+            return Ok(result);
+        };
+        Ok(Self::apply(result, candidates.len(), &ranking.ranking))
+    }
+
+    //按分数顺序选出交给LLM的候选描述，超出token预算时停止
+    fn candidates(&self, result: &RetrievalResult) -> Vec<String> {
+        let mut budget = self.max_prompt_tokens;
+        result
+            .memories()
+            .iter()
+            .take(self.max_candidates)
+            .map(|memory| serde_json::to_string(memory.mem_type()).unwrap_or_default())
+            .take_while(|description| {
+                let cost = description.chars().count();
+                if cost > budget {
+                    return false;
+                }
+                budget -= cost;
+                true
+            })
+            .collect()
+    }
+
+    //把前count条记忆按ranking重排，忽略越界与重复的下标
+    fn apply(result: RetrievalResult, count: usize, ranking: &[usize]) -> RetrievalResult {
+        let memories = result.into_memories();
+        let scores = memories
+            .iter()
+            .map(|memory| memory.score())
+            .collect::<Vec<_>>();
+        let mut seen = HashSet::new();
+        let ranked = ranking
+            .iter()
+            .copied()
+            .filter(|&index| index < count && seen.insert(index))
+            .collect::<Vec<_>>();
+        let reranked = ranked
+            .into_iter()
+            .chain((0..memories.len()).filter(|index| !seen.contains(index)))
+            .zip(scores)
+            .map(|(index, score)| {
+                let memory = &memories[index];
+                RetrievedMemory::new(
+                    memory.id(),
+                    score,
+                    memory.mem_type().clone(),
+                    memory.provenance().clone(),
+                )
+            })
+            .collect();
+        RetrievalResult::new(reranked)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Ranking {
+    ranking: Vec<usize>,
+}
+
+//重排用的提示词，候选以[下标]编号
+struct RerankPrompt {
+    prompt: ChatPrompt,
+}
+impl RerankPrompt {
+    fn new(turn: &str, candidates: &[String]) -> Self {
+        let candidates = candidates
+            .iter()
+            .enumerate()
+            .map(|(index, description)| format!("[{index}] {description}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Self {
+            prompt: ChatPrompt::new(
+                "You are ranking memories recalled for a conversation. Order the candidate memories by how useful they are for replying to the current turn, most useful first. \
+                 Reply with JSON only: {\"ranking\": [index, ...]}",
+                &format!("Current turn: {turn}\nCandidate memories:\n{candidates}"),
+            ),
+        }
+    }
+}
+impl PromptBuilder for RerankPrompt {
+    fn build_prompt(&mut self) -> Vec<ChatCompletionRequestMessage> {
+        self.prompt.build_prompt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::algo::retrieve::Provenance;
+    use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+    use crate::memory::memory_note::{MemoryId, MemoryType};
+    use crate::memory::working_memory::llm::mock::MockLlmServer;
+
+    fn prepare(contents: &[&str]) -> (RetrievalResult, Vec<MemoryId>) {
+        let ids = contents.iter().map(|_| MemoryId::new()).collect::<Vec<_>>();
+        let memories = contents
+            .iter()
+            .zip(&ids)
+            .enumerate()
+            .map(|(i, (content, &id))| {
+                RetrievedMemory::new(
+                    id,
+                    0.9 - 0.1 * i as f32,
+                    MemoryType::Semantic(SemMemory::new(
+                        content.to_string(),
+                        ConceptType::Entity,
+                        format!("关于{content}的记忆"),
+                    )),
+                    Provenance::Similarity,
+                )
+            })
+            .collect();
+        (RetrievalResult::new(memories), ids)
+    }
+
+    #[tokio::test]
+    async fn test_rerank_follows_llm_ranking() {
+        let (result, ids) = prepare(&["咖啡", "文玩", "火锅", "散步"]);
+        let server = MockLlmServer::start(vec![
+            "```json\n{\"ranking\": [2, 0, 2, 9]}\n```".to_string(),
+        ])
+        .await;
+
+        let reranked = LlmReranker::new(Arc::new(server.client()))
+            .rerank("今晚想吃点辣的", result.clone())
+            .await
+            .unwrap();
+        //未被提及的候选按原顺序排在后面，分数沿用原来的分数序列
+        assert_eq!(
+            reranked.ids().collect::<Vec<_>>(),
+            vec![ids[2], ids[0], ids[1], ids[3]]
+        );
+        assert_eq!(
+            reranked
+                .memories()
+                .iter()
+                .map(|m| m.score())
+                .collect::<Vec<_>>(),
+            result
+                .memories()
+                .iter()
+                .map(|m| m.score())
+                .collect::<Vec<_>>()
+        );
+        assert!(server.requests()[0].contains("今晚想吃点辣的"));
+    }
+
+    #[tokio::test]
+    async fn test_rerank_falls_back_on_invalid_reply() {
+        let (result, _) = prepare(&["咖啡", "文玩", "火锅"]);
+        let server = MockLlmServer::start(vec!["火锅最相关".to_string()]).await;
+
+        let reranked = LlmReranker::new(Arc::new(server.client()))
+            .rerank("今晚想吃点辣的", result.clone())
+            .await
+            .unwrap();
+        assert_eq!(reranked, result);
+    }
+
+    #[tokio::test]
+    async fn test_rerank_caps_candidates() {
+        let (result, ids) = prepare(&["咖啡", "文玩", "火锅", "散步"]);
+        let server = MockLlmServer::start(vec![r#"{"ranking": [1, 3]}"#.to_string()]).await;
+
+        let reranked = LlmReranker::new(Arc::new(server.client()))
+            .with_max_candidates(2)
+            .rerank("最近的爱好", result)
+            .await
+            .unwrap();
+        //只有前两条交给LLM，越界的下标被忽略
+        assert_eq!(
+            reranked.ids().collect::<Vec<_>>(),
+            vec![ids[1], ids[0], ids[2], ids[3]]
+        );
+        let request = &server.requests()[0];
+        assert!(request.contains("[1]"));
+        assert!(!request.contains("[2]"));
+
+        //token预算不足以容纳两条候选时不调用LLM
+        let (result, _) = prepare(&["咖啡", "文玩"]);
+        let reranked = LlmReranker::new(Arc::new(server.client()))
+            .with_max_prompt_tokens(10)
+            .rerank("最近的爱好", result.clone())
+            .await
+            .unwrap();
+        assert_eq!(reranked, result);
+        assert_eq!(server.requests().len(), 1);
+    }
+}
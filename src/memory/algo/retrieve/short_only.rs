@@ -1,18 +1,90 @@
 use crate::memory::working_memory::WorkingMemory;
+use async_trait::async_trait;
 use std::sync::Arc;
 
 //仅提取短期记忆策略，即仅提取滑动窗口
-use super::RetrStrategy;
+use super::{AsyncRetrStrategy, RetrResult, RetrievalResult};
 pub struct RetrShortOnly {
-    clipping_length: Option<usize>,
+    clipping_length: Option<usize>, //只保留窗口中最近的若干条信息
     include_summary: bool,
 }
+impl RetrShortOnly {
+    pub fn new(clipping_length: Option<usize>, include_summary: bool) -> Self {
+        Self {
+            clipping_length,
+            include_summary,
+        }
+    }
+}
 pub struct ShortOnlyRequest {
     working_mem: Arc<WorkingMemory>, //因为检索算法很可能需要并发执行，使用Arc而非引用确保可以Send
 }
-impl RetrStrategy for RetrShortOnly {
+impl ShortOnlyRequest {
+    pub fn new(working_mem: Arc<WorkingMemory>) -> Self {
+        Self { working_mem }
+    }
+}
+#[async_trait]
+impl AsyncRetrStrategy for RetrShortOnly {
     type RetrRequest = ShortOnlyRequest;
-    fn retrieve(&self, request: Self::RetrRequest) -> Vec<String> {
-        todo!()
+    async fn retrieve_async(&self, request: Self::RetrRequest) -> RetrResult<RetrievalResult> {
+        let window = request.working_mem.sliding_window();
+        let mut short_term = Vec::with_capacity(window.len() + 1);
+        if self.include_summary {
+            let summary = window.summary().await;
+            if !summary.is_empty() {
+                short_term.push(summary);
+            }
+        }
+        let skip = self
+            .clipping_length
+            .map(|len| window.len().saturating_sub(len))
+            .unwrap_or(0);
+        short_term.extend(
+            window
+                .iter()
+                .skip(skip)
+                .map(|info| info.get_str().to_string()),
+        );
+        Ok(RetrievalResult::from_short_term(short_term))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::memory_cluster::MemoryCluster;
+    use crate::memory::working_memory::llm::{client::LlmClient, config::LLMConfig};
+    use crate::memory::working_memory::sliding_window::SlidingWindow;
+
+    #[tokio::test]
+    async fn test_short_only_clipping() {
+        //窗口未满时不会触发摘要，因此不需要真实的LLM
+        let client = LlmClient::new(LLMConfig::new("", "http://127.0.0.1:1", ""));
+        let mut window = SlidingWindow::new(10);
+        for (text, role) in [
+            ("first", "user"),
+            ("second", "assistant"),
+            ("third", "user"),
+        ] {
+            window.push(text, role, &client).await.unwrap();
+        }
+        let working_mem = Arc::new(WorkingMemory::new(MemoryCluster::new(), window));
+
+        let result = RetrShortOnly::new(Some(2), false)
+            .retrieve_async(ShortOnlyRequest::new(working_mem.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            result.short_term(),
+            &["second".to_string(), "third".to_string()]
+        );
+        assert!(result.memories().is_empty());
+
+        let result = RetrShortOnly::new(None, true)
+            .retrieve_async(ShortOnlyRequest::new(working_mem))
+            .await
+            .unwrap();
+        assert_eq!(result.short_term().len(), 3);
     }
 }
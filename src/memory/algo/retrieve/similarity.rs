@@ -1,17 +1,412 @@
 //仅提取相似记忆策略，即仅提取相似度大于阈值的记忆片段
-use super::RetrStrategy;
+use super::{Provenance, RetrResult, RetrStrategy, RetrievalResult};
+use crate::memory::algo::hnsw::EmbeddingFacet;
+use crate::memory::embedding::query::note::{
+    MemoryRetrieveQueryEmbedding, MemoryRetrieveQueryVariantEmbedding,
+};
+use crate::memory::embedding::{Embeddable, EmbeddingModel};
+use crate::memory::memory_cluster::MemoryCluster;
+use crate::memory::memory_note::MemoryId;
+use crate::memory::query::compute::{ComputeContext, QueryCompute};
+use crate::memory::query::retrieve::MemoryRetrieveQuery;
+use crate::memory::query::scoring::ScoringProfile;
 use crate::memory::working_memory::WorkingMemory;
+use rayon::prelude::*;
+use std::collections::HashSet;
 use std::sync::Arc;
 pub struct RetrSimilarity {
     similarity_threshold: f64,
     max_results: usize,
+    model: Arc<dyn EmbeddingModel + Send + Sync>,
+    ann_candidates: Option<usize>, //若设置，则先用ANN索引为每个查询分量召回k个候选，仅对候选精确计分
+    profile: ScoringProfile,
+}
+impl RetrSimilarity {
+    pub fn new(
+        model: Arc<dyn EmbeddingModel + Send + Sync>,
+        similarity_threshold: f64,
+        max_results: usize,
+    ) -> Self {
+        Self {
+            similarity_threshold,
+            max_results,
+            model,
+            ann_candidates: None,
+            profile: ScoringProfile::default(),
+        }
+    }
+    pub fn with_scoring_profile(mut self, profile: ScoringProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+    pub fn with_ann_candidates(mut self, k: usize) -> Self {
+        self.ann_candidates = Some(k.max(1));
+        self
+    }
+    pub fn similarity_threshold(&self) -> f64 {
+        self.similarity_threshold
+    }
+    pub fn max_results(&self) -> usize {
+        self.max_results
+    }
+    pub fn model(&self) -> &Arc<dyn EmbeddingModel + Send + Sync> {
+        &self.model
+    }
+    pub fn ann_candidates(&self) -> Option<usize> {
+        self.ann_candidates
+    }
+    pub fn scoring_profile(&self) -> &ScoringProfile {
+        &self.profile
+    }
+
+    /// 并行计算cluster中所有记忆与查询的分数（归一化到[0, 1]），返回阈值以上、按分数降序的前max_results个，
+    /// 不满足查询结构化过滤或硬性时间约束的记忆不参与计分
+    pub fn search(
+        &self,
+        cluster: &MemoryCluster,
+        query: &MemoryRetrieveQueryEmbedding,
+    ) -> RetrResult<Vec<(MemoryId, f32)>> {
+        self.search_top(cluster, query, self.max_results)
+    }
+
+    /// 与search相同，但返回前limit个结果
+    pub fn search_top(
+        &self,
+        cluster: &MemoryCluster,
+        query: &MemoryRetrieveQueryEmbedding,
+        limit: usize,
+    ) -> RetrResult<Vec<(MemoryId, f32)>> {
+        let candidates = self
+            .ann_candidates
+            .map(|k| Self::ann_candidate_set(cluster, query, k));
+        let notes = cluster
+            .embedded_notes()
+            .filter(|note| {
+                candidates
+                    .as_ref()
+                    .is_none_or(|candidates| candidates.contains(&note.note.id()))
+                    && query.accepts(cluster, note)
+            })
+            .collect::<Vec<_>>();
+        //同一次检索中的所有记忆使用同一个now计分
+        let ctx = ComputeContext::new(&self.profile);
+        let mut scored = notes
+            .par_iter()
+            .map(|note| note.compute(query, &ctx))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|result| result.score as f64 >= self.similarity_threshold)
+            .map(|result| (result.id, result.score))
+            .collect::<Vec<_>>();
+
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored.truncate(limit);
+        Ok(scored)
+    }
+
+    //按查询的各个分量分别在对应的ANN索引中召回候选，取并集
+    fn ann_candidate_set(
+        cluster: &MemoryCluster,
+        query: &MemoryRetrieveQueryEmbedding,
+        k: usize,
+    ) -> HashSet<MemoryId> {
+        let mut lookups = vec![(EmbeddingFacet::Tag, query.tag())];
+        match query.variant() {
+            MemoryRetrieveQueryVariantEmbedding::Semantic(units) => lookups.extend(
+                units
+                    .iter()
+                    .filter_map(|unit| unit.concept_identifier())
+                    .map(|vector| (EmbeddingFacet::SemanticContent, vector)),
+            ),
+            MemoryRetrieveQueryVariantEmbedding::Situation(units) => lookups.extend(
+                units
+                    .iter()
+                    .filter_map(|unit| unit.narrative())
+                    .map(|vector| (EmbeddingFacet::SituationNarrative, vector)),
+            ),
+        }
+        lookups
+            .into_iter()
+            .flat_map(|(facet, vector)| cluster.search_similar(facet, vector, k))
+            .map(|(id, _)| id)
+            .collect()
+    }
 }
 pub struct SimilarityRequest {
     working_mem: Arc<WorkingMemory>,
+    query: MemoryRetrieveQuery,
+}
+impl SimilarityRequest {
+    pub fn new(working_mem: Arc<WorkingMemory>, query: MemoryRetrieveQuery) -> Self {
+        Self { working_mem, query }
+    }
 }
 impl RetrStrategy for RetrSimilarity {
     type RetrRequest = SimilarityRequest;
-    fn retrieve(&self, request: Self::RetrRequest) -> Vec<String> {
-        todo!()
+    fn retrieve(&self, request: Self::RetrRequest) -> RetrResult<RetrievalResult> {
+        let query_embedding = request.query.embed(self.model.as_ref())?;
+        let cluster = request.working_mem.cluster();
+        let scored = self.search(cluster, &query_embedding)?;
+        Ok(RetrievalResult::from_scored(
+            cluster,
+            scored,
+            Provenance::Similarity,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+    use crate::memory::embedding::note::EmbeddedMemoryNote;
+    use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+    use crate::memory::memory_note::{MemoryKind, MemoryNoteBuilder, MemoryType};
+    use crate::memory::query::filter::MemoryFilter;
+    use crate::memory::query::retrieve::{
+        MemoryRetrieveQueryVariant, SemanticQueryUnit, SituationQueryUnit,
+    };
+    use crate::memory::working_memory::sliding_window::SlidingWindow;
+
+    fn prepare_working_mem(model: &BgeSmallZh, contents: &[&str]) -> Arc<WorkingMemory> {
+        let mut cluster = MemoryCluster::new();
+        for content in contents {
+            let mut memory = SemMemory::new(
+                content.to_string(),
+                ConceptType::Entity,
+                format!("关于{content}的记忆"),
+            );
+            memory.aliases = vec![content.to_string()];
+            let note = MemoryNoteBuilder::new(MemoryType::Semantic(memory))
+                .tags(vec!["日常".to_string()])
+                .build()
+                .unwrap()
+                .embed_and_fuse(model)
+                .unwrap();
+            cluster.add_single_node(note).unwrap();
+        }
+        Arc::new(WorkingMemory::new(cluster, SlidingWindow::new(4)))
+    }
+
+    fn semantic_query(concept: &str) -> MemoryRetrieveQuery {
+        MemoryRetrieveQuery::new(
+            vec!["日常".to_string()],
+            MemoryRetrieveQueryVariant::make_semantic(vec![
+                SemanticQueryUnit::new().with_concept_identifier(concept.to_string()),
+            ]),
+        )
+    }
+
+    #[test]
+    fn test_similarity_retrieve_threshold_and_limit() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let working_mem = prepare_working_mem(&model, &["咖啡", "咖啡机", "文玩", "麻辣烫"]);
+
+        let retr = RetrSimilarity::new(model.clone(), 0.0, 2);
+        let result = retr
+            .retrieve(SimilarityRequest::new(
+                working_mem.clone(),
+                semantic_query("咖啡"),
+            ))
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        assert!(result.memories()[0].score() >= result.memories()[1].score());
+        assert!(
+            result
+                .memories()
+                .iter()
+                .all(|memory| memory.provenance() == &Provenance::Similarity)
+        );
+
+        let retr = RetrSimilarity::new(model.clone(), 1.5, 10);
+        let result = retr
+            .retrieve(SimilarityRequest::new(
+                working_mem.clone(),
+                semantic_query("咖啡"),
+            ))
+            .unwrap();
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_similarity_scores_across_memory_types() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let working_mem = prepare_working_mem(&model, &["咖啡"]);
+        let cluster = working_mem.cluster();
+        assert_eq!(cluster.embedded_notes().count(), 1);
+
+        let query = MemoryRetrieveQuery::new(
+            vec!["日常".to_string()],
+            MemoryRetrieveQueryVariant::make_situation(vec![
+                SituationQueryUnit::new().with_narrative("喝咖啡".to_string()),
+            ]),
+        )
+        .embed(model.as_ref())
+        .unwrap();
+        let retr = RetrSimilarity::new(model.clone(), 0.0, 10);
+        let scored = retr.search(cluster, &query).unwrap();
+        assert_eq!(scored.len(), 1);
+        //tag完全一致贡献0.4，语义记忆与情境叙述的跨类型比较贡献其余部分
+        assert!(scored[0].1 > 0.4);
+        assert!(scored[0].1 <= 1.0);
+    }
+
+    //每条记忆以自身内容为唯一的标签，使ANN在各分量上的最近邻都是确定的
+    fn distinct_notes(model: &BgeSmallZh, contents: &[&str]) -> Vec<EmbeddedMemoryNote> {
+        contents
+            .iter()
+            .map(|content| {
+                MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+                    content.to_string(),
+                    ConceptType::Entity,
+                    format!("关于{content}的记忆"),
+                )))
+                .tags(vec![content.to_string()])
+                .build()
+                .unwrap()
+                .embed_and_fuse(model)
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn distinct_query(concept: &str) -> MemoryRetrieveQuery {
+        MemoryRetrieveQuery::new(
+            vec![concept.to_string()],
+            MemoryRetrieveQueryVariant::make_semantic(vec![
+                SemanticQueryUnit::new().with_concept_identifier(concept.to_string()),
+            ]),
+        )
+    }
+
+    #[test]
+    fn test_similarity_with_ann_candidates() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let notes = distinct_notes(&model, &["咖啡", "咖啡机", "文玩", "麻辣烫"]);
+        let ids = notes
+            .iter()
+            .map(|note| note.note().id())
+            .collect::<Vec<_>>();
+        let mut cluster = MemoryCluster::new();
+        cluster.merge(notes);
+        let query = distinct_query("咖啡").embed(model.as_ref()).unwrap();
+
+        //标签与概念都只召回“咖啡”本身
+        assert_eq!(
+            RetrSimilarity::ann_candidate_set(&cluster, &query, 1),
+            HashSet::from([ids[0]])
+        );
+        let approx = RetrSimilarity::new(model.clone(), 0.0, 10)
+            .with_ann_candidates(1)
+            .search(&cluster, &query)
+            .unwrap();
+        assert_eq!(
+            approx.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![ids[0]]
+        );
+        //候选覆盖全部记忆时与精确检索相同
+        let exact = RetrSimilarity::new(model.clone(), 0.0, 10)
+            .search(&cluster, &query)
+            .unwrap();
+        let approx = RetrSimilarity::new(model.clone(), 0.0, 10)
+            .with_ann_candidates(4)
+            .search(&cluster, &query)
+            .unwrap();
+        assert_eq!(exact, approx);
+    }
+
+    #[test]
+    fn test_ann_index_follows_remove_and_merge() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let notes = distinct_notes(&model, &["咖啡", "咖啡机", "文玩", "麻辣烫"]);
+        let ids = notes
+            .iter()
+            .map(|note| note.note().id())
+            .collect::<Vec<_>>();
+        let coffee = notes[0].clone();
+        let mut cluster = MemoryCluster::new();
+        cluster.merge(notes);
+        let query = distinct_query("咖啡").embed(model.as_ref()).unwrap();
+        let facets = [EmbeddingFacet::Tag, EmbeddingFacet::SemanticContent];
+        for facet in facets {
+            assert_eq!(cluster.ann_index().facet(facet).len(), 4);
+        }
+
+        //删除后索引中不再有该记忆，最近邻变为“咖啡机”
+        assert!(cluster.remove_single_node(ids[0]).is_some());
+        for facet in facets {
+            assert!(!cluster.ann_index().facet(facet).contains(ids[0]));
+            assert_eq!(cluster.ann_index().facet(facet).len(), 3);
+        }
+        assert_eq!(
+            RetrSimilarity::ann_candidate_set(&cluster, &query, 1),
+            HashSet::from([ids[1]])
+        );
+
+        //重新并入后恢复
+        assert!(cluster.merge(vec![coffee]).is_empty());
+        for facet in facets {
+            assert!(cluster.ann_index().facet(facet).contains(ids[0]));
+            assert_eq!(cluster.ann_index().facet(facet).len(), 4);
+        }
+        assert_eq!(
+            RetrSimilarity::ann_candidate_set(&cluster, &query, 1),
+            HashSet::from([ids[0]])
+        );
+    }
+
+    #[test]
+    fn test_similarity_structured_filter() {
+        let model = Arc::new(BgeSmallZh::default_cpu().unwrap());
+        let mut cluster = MemoryCluster::new();
+        let ids = ["咖啡", "文玩"]
+            .iter()
+            .map(|content| {
+                let note = MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+                    content.to_string(),
+                    ConceptType::Entity,
+                    format!("关于{content}的记忆"),
+                )))
+                .tags(vec!["日常".to_string()])
+                .build()
+                .unwrap()
+                .embed_and_fuse(model.as_ref())
+                .unwrap();
+                let id = note.note().id();
+                cluster.add_single_node(note).unwrap();
+                id
+            })
+            .collect::<Vec<_>>();
+        assert!(cluster.record_retrieval(ids[1]));
+
+        let retr = RetrSimilarity::new(model.clone(), 0.0, 10);
+        let search = |filter: MemoryFilter| {
+            let query = semantic_query("咖啡")
+                .with_filter(filter)
+                .embed(model.as_ref())
+                .unwrap();
+            retr.search(&cluster, &query).unwrap()
+        };
+
+        //过滤先于计分，即使相似度更高的记忆也会被排除
+        let retrieved = search(MemoryFilter::MinRetrievalCount(1));
+        assert_eq!(retrieved.len(), 1);
+        assert_eq!(retrieved[0].0, ids[1]);
+        assert!(search(MemoryFilter::Kind(MemoryKind::Situation)).is_empty());
+        assert_eq!(
+            search(
+                MemoryFilter::Tag("日常".to_string())
+                    .and(MemoryFilter::ConceptType(ConceptType::Entity))
+            )
+            .len(),
+            2
+        );
+        assert_eq!(
+            cluster
+                .filter_notes(&MemoryFilter::MinRetrievalCount(1))
+                .map(|note| note.id())
+                .collect::<Vec<_>>(),
+            vec![ids[1]]
+        );
     }
 }
@@ -0,0 +1,340 @@
+use std::sync::Arc;
+
+use petgraph::Direction;
+use petgraph::visit::{EdgeRef, NodeIndexable};
+
+use crate::memory::memory_cluster::MemoryCluster;
+use crate::memory::memory_links::MemoryLinkType;
+use crate::memory::memory_note::MemoryId;
+use crate::memory::working_memory::WorkingMemory;
+
+use super::{Provenance, RetrResult, RetrStrategy, RetrievalResult};
+
+//各类连接的传导率，与连接强度相乘作为扩散时的边权
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkConductance {
+    pub sem: f32,
+    pub proc: f32,
+    pub situation: f32,
+    pub cross: f32,
+}
+impl LinkConductance {
+    pub fn of(&self, link_type: &MemoryLinkType) -> f32 {
+        let conductance = match link_type {
+            MemoryLinkType::Sem(_) => self.sem,
+            MemoryLinkType::Proc(_) => self.proc,
+            MemoryLinkType::Situation(_) => self.situation,
+            MemoryLinkType::Cross(_) => self.cross,
+        };
+        conductance.max(0.0)
+    }
+}
+impl Default for LinkConductance {
+    fn default() -> Self {
+        Self {
+            sem: 1.0,
+            proc: 1.0,
+            situation: 1.0,
+            cross: 1.0,
+        }
+    }
+}
+
+//基于神经动力学的扩散激活：种子节点持续受到刺激，电位沿带权边扩散直到稳态
+pub struct RetrSpreading {
+    max_results: usize,
+    decay: f32,                 //每跳的衰减系数，必须小于1以保证收敛
+    conductance: LinkConductance,
+    convergence_threshold: f32, //两次迭代间电位变化（L1）小于此值视为达到稳态
+    max_iterations: usize,
+    self_loop: Option<f32>, //为每个节点添加的自环权重
+}
+impl RetrSpreading {
+    pub fn new(max_results: usize) -> Self {
+        Self {
+            max_results,
+            decay: 0.5,
+            conductance: LinkConductance::default(),
+            convergence_threshold: 1e-4,
+            max_iterations: 100,
+            self_loop: None,
+        }
+    }
+    pub fn with_decay(mut self, decay: f32) -> Self {
+        self.decay = decay.clamp(0.0, 1.0 - f32::EPSILON);
+        self
+    }
+    pub fn with_conductance(mut self, conductance: LinkConductance) -> Self {
+        self.conductance = conductance;
+        self
+    }
+    pub fn with_convergence_threshold(mut self, convergence_threshold: f32) -> Self {
+        self.convergence_threshold = convergence_threshold.max(f32::EPSILON);
+        self
+    }
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+    /// 为每个节点添加自环（成环处理），节点每次迭代保留部分自身电位，
+    /// 无环图中的节点因此也能形成回路，稳态电位不再只由单次传递决定
+    pub fn with_self_loop(mut self, weight: f32) -> Self {
+        self.self_loop = Some(weight.max(0.0));
+        self
+    }
+    pub fn max_results(&self) -> usize {
+        self.max_results
+    }
+    pub fn decay(&self) -> f32 {
+        self.decay
+    }
+    pub fn conductance(&self) -> &LinkConductance {
+        &self.conductance
+    }
+    pub fn convergence_threshold(&self) -> f32 {
+        self.convergence_threshold
+    }
+    pub fn max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+    pub fn self_loop(&self) -> Option<f32> {
+        self.self_loop
+    }
+
+    /// 以seeds为刺激源迭代求解稳态电位，返回按电位降序的top-k节点
+    ///
+    /// 动力学方程为 a(t+1) = s + decay * P^T a(t)，s为归一化的种子刺激，
+    /// P为按出边权重（传导率 * 连接强度，含自环）归一化的传递矩阵。
+    /// decay < 1 时方程存在唯一稳态；达到收敛阈值或迭代上限时停止。
+    pub fn spread(&self, cluster: &MemoryCluster, seeds: &[(MemoryId, f32)]) -> Vec<(MemoryId, f32)> {
+        let graph = cluster.graph();
+        let bound = graph.node_bound();
+
+        let mut stimulus = vec![0.0f32; bound];
+        for &(mem_id, weight) in seeds {
+            if weight <= 0.0 || !weight.is_finite() {
+                continue;
+            }
+            if let Some(index) = cluster.node_index(mem_id) {
+                stimulus[index.index()] += weight;
+            }
+        }
+        let stimulus_sum = stimulus.iter().sum::<f32>();
+        if stimulus_sum <= 0.0 {
+            return Vec::new();
+        }
+        stimulus.iter_mut().for_each(|s| *s /= stimulus_sum);
+
+        let self_loop = self.self_loop.unwrap_or(0.0);
+        let out_weight = graph
+            .node_indices()
+            .fold(vec![0.0f32; bound], |mut acc, index| {
+                acc[index.index()] = graph
+                    .edges_directed(index, Direction::Outgoing)
+                    .map(|edge| self.edge_weight(edge.weight().link_type()))
+                    .sum::<f32>()
+                    + self_loop;
+                acc
+            });
+
+        let mut potential = stimulus.clone();
+        for _ in 0..self.max_iterations {
+            let mut next = stimulus.clone();
+            for node in graph.node_indices() {
+                let (current, total) = (potential[node.index()], out_weight[node.index()]);
+                if current <= 0.0 || total <= 0.0 {
+                    continue;
+                }
+                let fired = self.decay * current / total;
+                for edge in graph.edges_directed(node, Direction::Outgoing) {
+                    next[edge.target().index()] +=
+                        fired * self.edge_weight(edge.weight().link_type());
+                }
+                next[node.index()] += fired * self_loop;
+            }
+            let delta = next
+                .iter()
+                .zip(&potential)
+                .map(|(a, b)| (a - b).abs())
+                .sum::<f32>();
+            potential = next;
+            if delta < self.convergence_threshold {
+                break;
+            }
+        }
+
+        let mut ranked = graph
+            .node_indices()
+            .filter(|index| potential[index.index()] > 0.0)
+            .filter_map(|index| {
+                graph
+                    .node_weight(index)
+                    .map(|note| (note.id(), potential[index.index()]))
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(self.max_results);
+        ranked
+    }
+
+    fn edge_weight(&self, link_type: &MemoryLinkType) -> f32 {
+        self.conductance.of(link_type) * link_type.strength()
+    }
+}
+
+pub struct SpreadingRequest {
+    working_mem: Arc<WorkingMemory>,
+    seeds: Vec<(MemoryId, f32)>, //种子节点及其初始刺激强度，通常来自相似度检索
+}
+impl SpreadingRequest {
+    pub fn new(working_mem: Arc<WorkingMemory>, seeds: Vec<(MemoryId, f32)>) -> Self {
+        Self { working_mem, seeds }
+    }
+}
+impl RetrStrategy for RetrSpreading {
+    type RetrRequest = SpreadingRequest;
+    fn retrieve(&self, request: Self::RetrRequest) -> RetrResult<RetrievalResult> {
+        let cluster = request.working_mem.cluster();
+        let ranked = self.spread(cluster, &request.seeds);
+        Ok(RetrievalResult::from_scored(
+            cluster,
+            ranked,
+            Provenance::Spreading,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::embedding::Embeddable;
+    use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+    use crate::memory::memory_links::MemoryLink;
+    use crate::memory::memory_links::proc_mem::{ProcMemLink, TrigToAction};
+    use crate::memory::memory_links::sem_mem::SemMemLink;
+    use crate::memory::memory_note::proc_mem::{Action, ActionType, ProcMemory};
+    use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+    use crate::memory::memory_note::{MemoryNoteBuilder, MemoryType};
+
+    fn sem_link(from: MemoryId, to: MemoryId) -> MemoryLink {
+        MemoryLink::new(
+            from,
+            to,
+            MemoryLinkType::Sem(SemMemLink::new("关联".to_string(), 1.0, 1.0)),
+        )
+    }
+
+    fn build_cluster(
+        model: &BgeSmallZh,
+        nodes: &[(MemoryId, &str)],
+        links: Vec<MemoryLink>,
+    ) -> MemoryCluster {
+        let mut cluster = MemoryCluster::new();
+        let notes = nodes
+            .iter()
+            .map(|&(id, content)| {
+                let node_links = links
+                    .iter()
+                    .filter(|link| link.from() == id)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+                    content.to_string(),
+                    ConceptType::Entity,
+                    format!("{content}的描述"),
+                )))
+                .id(id)
+                .mem_links(node_links)
+                .build()
+                .unwrap()
+                .embed_and_fuse(model)
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        cluster.merge(notes);
+        cluster
+    }
+
+    #[test]
+    fn test_spreading_decays_per_hop() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (a, b, c) = (MemoryId::new(), MemoryId::new(), MemoryId::new());
+        let cluster = build_cluster(
+            &model,
+            &[(a, "咖啡"), (b, "咖啡机"), (c, "礼物")],
+            vec![sem_link(a, b), sem_link(b, c), sem_link(c, a)],
+        );
+
+        let ranked = RetrSpreading::new(10).spread(&cluster, &[(a, 1.0)]);
+        assert_eq!(
+            ranked.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![a, b, c]
+        );
+        //稳态：a = 1 + 0.5c, b = 0.5a, c = 0.5b
+        assert!((ranked[0].1 - 8.0 / 7.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_spreading_link_conductance() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (a, b, c) = (MemoryId::new(), MemoryId::new(), MemoryId::new());
+        let proc_link = MemoryLink::new(
+            a,
+            c,
+            MemoryLinkType::Proc(ProcMemLink::TrigToAction(TrigToAction::new(1.0))),
+        );
+        let mut cluster = build_cluster(
+            &model,
+            &[(a, "铃声"), (b, "学校")],
+            vec![sem_link(a, b), proc_link],
+        );
+        //TrigToAction的目标必须是程序性记忆
+        cluster.add_single_node(
+            MemoryNoteBuilder::new(MemoryType::Procedure(ProcMemory::new(Action::new(
+                "逃跑".to_string(),
+                ActionType::new_speak(),
+            ))))
+            .id(c)
+            .build()
+            .unwrap()
+            .embed_and_fuse(&model)
+            .unwrap(),
+        )
+        .unwrap();
+
+        let ranked = RetrSpreading::new(10).spread(&cluster, &[(a, 1.0)]);
+        assert!(ranked.iter().any(|(id, _)| *id == c));
+
+        let retr = RetrSpreading::new(10).with_conductance(LinkConductance {
+            proc: 0.0,
+            ..Default::default()
+        });
+        let ranked = retr.spread(&cluster, &[(a, 1.0)]);
+        assert!(ranked.iter().any(|(id, _)| *id == b));
+        assert!(ranked.iter().all(|(id, _)| *id != c));
+    }
+
+    #[test]
+    fn test_spreading_self_loop_and_iteration_cap() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (a, b) = (MemoryId::new(), MemoryId::new());
+        let cluster = build_cluster(&model, &[(a, "雨天"), (b, "散步")], vec![sem_link(a, b)]);
+
+        let plain = RetrSpreading::new(10).spread(&cluster, &[(a, 1.0)]);
+        let looped = RetrSpreading::new(10)
+            .with_self_loop(1.0)
+            .spread(&cluster, &[(a, 1.0)]);
+        let score_of = |ranked: &[(MemoryId, f32)], id| {
+            ranked.iter().find(|(i, _)| *i == id).unwrap().1
+        };
+        assert!((score_of(&plain, a) - 1.0).abs() < 1e-4);
+        assert!(score_of(&looped, a) > score_of(&plain, a));
+        assert!(score_of(&looped, b) > score_of(&plain, b));
+
+        let capped = RetrSpreading::new(10)
+            .with_max_iterations(1)
+            .spread(&cluster, &[(a, 1.0)]);
+        assert_eq!(capped.len(), 2);
+        assert!(RetrSpreading::new(10).spread(&cluster, &[]).is_empty());
+    }
+}
@@ -4,6 +4,7 @@ use crate::memory::{
         EmbeddingGenResult, EmbeddingModel, EmbeddingVec,
     },
     memory_note::{MemoryNote, MemoryType},
+    record::Record,
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -18,6 +19,22 @@ impl MemoryEmbedding {
     pub fn variant(&self) -> &MemoryEmbeddingVariant {
         &self.variant
     }
+    /// 无结构查询（文本、原始向量）比较的关键向量：tag，以及语义内容或具体情境的叙述（若有）
+    pub fn key_vectors(&self) -> Vec<&EmbeddingVec> {
+        let content = match &self.variant {
+            MemoryEmbeddingVariant::Semantic(sem) => Some(sem.content()),
+            MemoryEmbeddingVariant::Situation(SituationEmbedding::Specific(specific)) => {
+                Some(specific.narrative())
+            }
+            _ => None,
+        };
+        std::iter::once(&self.tag).chain(content).collect()
+    }
+    /// 代表该记忆的单个向量：有内容向量时为内容向量，否则为tag
+    pub fn primary_vector(&self) -> &EmbeddingVec {
+        //SAFEUNWRAP: key_vectors总是包含tag
+        self.key_vectors().last().copied().unwrap()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -141,6 +158,32 @@ impl EmbeddedMemoryNote {
     pub fn into_tuple(self) -> (MemoryNote, MemoryEmbedding) {
         (self.note, self.embedding)
     }
+    pub fn view(&self) -> EmbeddedMemoryNoteRef<'_> {
+        EmbeddedMemoryNoteRef {
+            embedding: &self.embedding,
+            note: &self.note,
+            record: None,
+        }
+    }
+}
+
+//借用形式的EmbeddedMemoryNote，避免在检索大量节点时复制嵌入向量
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmbeddedMemoryNoteRef<'a> {
+    pub embedding: &'a MemoryEmbedding,
+    pub note: &'a MemoryNote,
+    pub record: Option<&'a Record>, //访问记录，计分时提供反馈信息
+}
+impl<'a> EmbeddedMemoryNoteRef<'a> {
+    pub fn note(&self) -> &'a MemoryNote {
+        self.note
+    }
+    pub fn embedding(&self) -> &'a MemoryEmbedding {
+        self.embedding
+    }
+    pub fn record(&self) -> Option<&'a Record> {
+        self.record
+    }
 }
 
 impl Embeddable for MemoryNote {
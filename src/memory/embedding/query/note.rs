@@ -1,9 +1,15 @@
 use crate::memory::{
     embedding::{
+        note::{EmbeddedMemoryNoteRef, MemoryEmbedding, MemoryEmbeddingVariant},
         query::{sem::SemanticQueryUnitEmbedding, situation::SituationQueryUnitEmbedding},
+        situation::SituationEmbedding,
         Embeddable, EmbeddingGenResult, EmbeddingModel, EmbeddingVec,
     },
-    query::retrieve::{MemoryRetrieveQuery, MemoryRetrieveQueryVariant},
+    memory_cluster::MemoryCluster,
+    query::{
+        filter::MemoryFilter,
+        retrieve::{MemoryRetrieveQuery, MemoryRetrieveQueryVariant},
+    },
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -54,6 +60,7 @@ impl Embeddable for MemoryRetrieveQueryVariant {
 pub struct MemoryRetrieveQueryEmbedding {
     tag: EmbeddingVec,
     variant: MemoryRetrieveQueryVariantEmbedding,
+    filter: Option<MemoryFilter>, //过滤表达式无需嵌入，直接随查询携带
 }
 impl MemoryRetrieveQueryEmbedding {
     pub fn tag(&self) -> &EmbeddingVec {
@@ -62,6 +69,31 @@ impl MemoryRetrieveQueryEmbedding {
     pub fn variant(&self) -> &MemoryRetrieveQueryVariantEmbedding {
         &self.variant
     }
+    pub fn filter(&self) -> Option<&MemoryFilter> {
+        self.filter.as_ref()
+    }
+    /// 记忆簇中的记忆是否通过查询的结构化过滤与硬性时间约束
+    pub fn accepts(&self, cluster: &MemoryCluster, note: &EmbeddedMemoryNoteRef<'_>) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches_in(cluster, note.note()))
+            && self.admits(note.embedding)
+    }
+    /// 记忆是否通过查询中的硬性时间约束，只要有一个情境查询单元接纳即可
+    pub fn admits(&self, embedding: &MemoryEmbedding) -> bool {
+        match &self.variant {
+            MemoryRetrieveQueryVariantEmbedding::Situation(units) if !units.is_empty() => {
+                let time = match embedding.variant() {
+                    MemoryEmbeddingVariant::Situation(SituationEmbedding::Specific(specific)) => {
+                        Some(specific.time_span())
+                    }
+                    _ => None,
+                };
+                units.iter().any(|unit| unit.admits(time))
+            }
+            _ => true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -82,6 +114,7 @@ impl Embeddable for MemoryRetrieveQuery {
         Ok(MemoryRetrieveQueryEmbedding {
             tag: tag_vec,
             variant: variant_vec,
+            filter: self.filter().cloned(),
         })
     }
     fn embed_and_fuse(
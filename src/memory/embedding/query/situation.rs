@@ -1,18 +1,23 @@
+use chrono::{DateTime, Utc};
+
 use crate::memory::{
     embedding::{
         query::situation::{
-            environment::EnvironmentQueryUnitEmbedding, event::EventQueryUnitEmbedding,
-            location::LocationQueryUnitEmbedding, participant::ParticipantQueryUnitEmbedding,
+            emotion::EmotionQueryUnitEmbedding, environment::EnvironmentQueryUnitEmbedding,
+            event::EventQueryUnitEmbedding, location::LocationQueryUnitEmbedding,
+            participant::ParticipantQueryUnitEmbedding, sensory::SensoryQueryUnitEmbedding,
         },
         vec_batch_embed, Embeddable, EmbeddingVec,
     },
-    query::retrieve::SituationQueryUnit,
+    query::retrieve::{SituationQueryUnit, TimeSpanQueryUnit},
 };
 
+pub mod emotion;
 pub mod environment;
 pub mod event;
 pub mod location;
 pub mod participant;
+pub mod sensory;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SituationQueryUnitEmbedding {
@@ -21,6 +26,9 @@ pub struct SituationQueryUnitEmbedding {
     participants: Option<ParticipantQueryUnitEmbedding>,
     environment: Option<EnvironmentQueryUnitEmbedding>,
     event: Option<EventQueryUnitEmbedding>,
+    emotion: Option<EmotionQueryUnitEmbedding>,
+    sensory: Option<SensoryQueryUnitEmbedding>,
+    time_span: Vec<TimeSpanQueryUnit>, //时间约束无需嵌入，直接随查询携带，多个区间之间为“或”关系
 }
 impl SituationQueryUnitEmbedding {
     pub fn narrative(&self) -> Option<&EmbeddingVec> {
@@ -38,6 +46,37 @@ impl SituationQueryUnitEmbedding {
     pub fn event(&self) -> Option<&EventQueryUnitEmbedding> {
         self.event.as_ref()
     }
+    pub fn emotion(&self) -> Option<&EmotionQueryUnitEmbedding> {
+        self.emotion.as_ref()
+    }
+    pub fn sensory(&self) -> Option<&SensoryQueryUnitEmbedding> {
+        self.sensory.as_ref()
+    }
+    pub fn time_span(&self) -> &[TimeSpanQueryUnit] {
+        &self.time_span
+    }
+    pub fn has_time_constraint(&self) -> bool {
+        !self.time_span.is_empty()
+    }
+    /// 硬过滤：存在硬约束区间时，记忆时间必须落在某个区间内（或存在软约束区间）才被接纳；
+    /// 不具有时间属性的记忆（time为None）无法满足硬约束
+    pub fn admits(&self, time: Option<&DateTime<Utc>>) -> bool {
+        self.time_span.is_empty()
+            || self
+                .time_span
+                .iter()
+                .any(|span| !span.is_hard() || time.is_some_and(|time| span.contains(time)))
+    }
+    /// 时间匹配分数，取所有区间中的最大值；没有时间约束时为1
+    pub fn time_score(&self, time: &DateTime<Utc>) -> f32 {
+        if self.time_span.is_empty() {
+            return 1.0;
+        }
+        self.time_span
+            .iter()
+            .map(|span| span.score(time))
+            .fold(0.0, f32::max)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -98,12 +137,37 @@ impl Embeddable for SituationQueryUnit {
             .transpose()?
             .flatten();
 
+        //emotion
+        let emotion_vecs = self
+            .emotion()
+            .map(|emotions| vec_batch_embed(emotions, model))
+            .transpose()?;
+
+        let fused_emotion_vec = emotion_vecs
+            .map(|vecs| EmotionQueryUnitEmbedding::weight_pooling(&vecs))
+            .transpose()?
+            .flatten();
+
+        //sensory
+        let sensory_vecs = self
+            .sensory()
+            .map(|sensories| vec_batch_embed(sensories, model))
+            .transpose()?;
+
+        let fused_sensory_vec = sensory_vecs
+            .map(|vecs| SensoryQueryUnitEmbedding::weight_pooling(&vecs))
+            .transpose()?
+            .flatten();
+
         Ok(SituationQueryUnitEmbedding {
             narrative: narrative_vec,
             location: fused_location_vec,
             participants: fused_participant_vec,
             environment: environment_vec,
             event: fused_event_vec,
+            emotion: fused_emotion_vec,
+            sensory: fused_sensory_vec,
+            time_span: self.time_span().cloned().unwrap_or_default(),
         })
     }
     fn embed_and_fuse(
@@ -124,8 +188,8 @@ mod tests {
     use crate::memory::{
         embedding::embedding_model::bge::BgeSmallZh,
         query::retrieve::{
-            EnvironmentQueryUnit, EventQueryUnit, LocationQueryUnit, ParticipantQueryUnit,
-            TimeSpanQueryUnit,
+            EmotionQueryUnit, EnvironmentQueryUnit, EventQueryUnit, LocationQueryUnit,
+            ParticipantQueryUnit, SensoryQueryUnit, TimeSpanQueryUnit,
         },
     };
 
@@ -162,6 +226,11 @@ mod tests {
                     .with_name("name")
                     .with_role("role"),
             ])
+            .with_emotion(vec![
+                EmotionQueryUnit::new("emotion").with_intensity(0.8),
+                EmotionQueryUnit::new("emotion"),
+            ])
+            .with_sensory(vec![SensoryQueryUnit::new("sensory")])
             .with_time_span(vec![
                 TimeSpanQueryUnit::new()
                     .with_start(DateTime::from_timestamp_nanos(100))
@@ -173,6 +242,8 @@ mod tests {
 
         let model = BgeSmallZh::default_cpu().unwrap();
 
-        situation.embed_and_fuse(&model).unwrap();
+        let embedded = situation.embed_and_fuse(&model).unwrap();
+        assert_eq!(embedded.embedding.emotion().unwrap().intensity(), Some(0.8));
+        assert_eq!(embedded.embedding.sensory().unwrap().intensity(), None);
     }
 }
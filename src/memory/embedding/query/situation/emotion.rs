@@ -0,0 +1,94 @@
+use crate::memory::{
+    embedding::{Embeddable, EmbeddingCalcError, EmbeddingCalcResult, EmbeddingVec},
+    query::retrieve::EmotionQueryUnit,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmotionQueryUnitEmbedding {
+    emotion: EmbeddingVec,
+    intensity: Option<f32>, //期望的强度，范围[0, 1]
+}
+impl EmotionQueryUnitEmbedding {
+    pub fn emotion(&self) -> &EmbeddingVec {
+        &self.emotion
+    }
+    pub fn intensity(&self) -> Option<f32> {
+        self.intensity
+    }
+    /// 按强度加权池化（未给出强度的单元权重为1），与记忆端情绪的池化方式一致；
+    /// 融合后的期望强度为给出强度的单元的平均值
+    pub fn weight_pooling(vecs: &[EmotionQueryUnitEmbedding]) -> EmbeddingCalcResult<Option<Self>> {
+        if vecs.is_empty() {
+            return Ok(None);
+        }
+        let (emotion, intensity) = intensity_pooling(
+            &vecs
+                .iter()
+                .map(|vec| (vec.emotion(), vec.intensity()))
+                .collect::<Vec<_>>(),
+        )?;
+        Ok(Some(Self { emotion, intensity }))
+    }
+}
+
+/// 情绪与感官查询单元共用的池化：向量按强度加权平均，强度取已给出值的平均
+pub(super) fn intensity_pooling(
+    units: &[(&EmbeddingVec, Option<f32>)],
+) -> EmbeddingCalcResult<(EmbeddingVec, Option<f32>)> {
+    let len = units[0].0.shape();
+    if !units.iter().all(|(vec, _)| vec.shape() == len) {
+        return Err(EmbeddingCalcError::ShapeMismatch);
+    }
+    let weight_of = |intensity: Option<f32>| intensity.unwrap_or(1.0).max(f32::EPSILON);
+    let weight_sum = units
+        .iter()
+        .map(|(_, intensity)| weight_of(*intensity))
+        .sum::<f32>();
+    let fused = units.iter().fold(vec![0.0; len], |acc, (vec, intensity)| {
+        acc.iter()
+            .zip(vec.iter())
+            .map(|(&a, &b)| a + b * weight_of(*intensity) / weight_sum)
+            .collect()
+    });
+
+    let given = units
+        .iter()
+        .filter_map(|(_, intensity)| *intensity)
+        .collect::<Vec<_>>();
+    let intensity = if given.is_empty() {
+        None
+    } else {
+        Some(given.iter().sum::<f32>() / given.len() as f32)
+    };
+    Ok((EmbeddingVec::new(fused), intensity))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbedEmotionQueryUnit {
+    pub embedding: EmotionQueryUnitEmbedding,
+    pub query: EmotionQueryUnit,
+}
+
+impl Embeddable for EmotionQueryUnit {
+    type EmbeddingGen = EmotionQueryUnitEmbedding;
+    type EmbeddingFused = EmbedEmotionQueryUnit;
+    fn embed(
+        &self,
+        model: &dyn crate::memory::embedding::EmbeddingModel,
+    ) -> crate::memory::embedding::EmbeddingGenResult<Self::EmbeddingGen> {
+        let [emotion_vec] = model.infer_batch(&vec![self.name()])?.try_into().unwrap(); //SAFEUNWRAP: 此处长度必为1
+        Ok(EmotionQueryUnitEmbedding {
+            emotion: emotion_vec,
+            intensity: self.intensity(),
+        })
+    }
+    fn embed_and_fuse(
+        self,
+        model: &dyn crate::memory::embedding::EmbeddingModel,
+    ) -> crate::memory::embedding::EmbeddingGenResult<Self::EmbeddingFused> {
+        Ok(EmbedEmotionQueryUnit {
+            embedding: self.embed(model)?,
+            query: self,
+        })
+    }
+}
@@ -0,0 +1,62 @@
+use crate::memory::{
+    embedding::{
+        query::situation::emotion::intensity_pooling, Embeddable, EmbeddingCalcResult, EmbeddingVec,
+    },
+    query::retrieve::SensoryQueryUnit,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensoryQueryUnitEmbedding {
+    sensory: EmbeddingVec,
+    intensity: Option<f32>, //期望的强度，范围[0, 1]
+}
+impl SensoryQueryUnitEmbedding {
+    pub fn sensory(&self) -> &EmbeddingVec {
+        &self.sensory
+    }
+    pub fn intensity(&self) -> Option<f32> {
+        self.intensity
+    }
+    pub fn weight_pooling(vecs: &[SensoryQueryUnitEmbedding]) -> EmbeddingCalcResult<Option<Self>> {
+        if vecs.is_empty() {
+            return Ok(None);
+        }
+        let (sensory, intensity) = intensity_pooling(
+            &vecs
+                .iter()
+                .map(|vec| (vec.sensory(), vec.intensity()))
+                .collect::<Vec<_>>(),
+        )?;
+        Ok(Some(Self { sensory, intensity }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbedSensoryQueryUnit {
+    pub embedding: SensoryQueryUnitEmbedding,
+    pub query: SensoryQueryUnit,
+}
+
+impl Embeddable for SensoryQueryUnit {
+    type EmbeddingGen = SensoryQueryUnitEmbedding;
+    type EmbeddingFused = EmbedSensoryQueryUnit;
+    fn embed(
+        &self,
+        model: &dyn crate::memory::embedding::EmbeddingModel,
+    ) -> crate::memory::embedding::EmbeddingGenResult<Self::EmbeddingGen> {
+        let [sensory_vec] = model.infer_batch(&vec![self.name()])?.try_into().unwrap(); //SAFEUNWRAP: 此处长度必为1
+        Ok(SensoryQueryUnitEmbedding {
+            sensory: sensory_vec,
+            intensity: self.intensity(),
+        })
+    }
+    fn embed_and_fuse(
+        self,
+        model: &dyn crate::memory::embedding::EmbeddingModel,
+    ) -> crate::memory::embedding::EmbeddingGenResult<Self::EmbeddingFused> {
+        Ok(EmbedSensoryQueryUnit {
+            embedding: self.embed(model)?,
+            query: self,
+        })
+    }
+}
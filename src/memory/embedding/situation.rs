@@ -5,6 +5,8 @@ pub mod event;
 pub mod location;
 pub mod participant;
 pub mod sensory_data;
+use chrono::{DateTime, Utc};
+
 use crate::memory::{
     embedding::{
         situation::{
@@ -45,6 +47,7 @@ impl From<AbstractSituationEmbedding> for SituationEmbedding {
 pub struct SpecificSituationEmbedding {
     narrative: EmbeddingVec,
     context: ContextEmbedding,
+    time_span: DateTime<Utc>, //时间不参与嵌入，随嵌入携带用于时间约束的匹配
 }
 impl SpecificSituationEmbedding {
     pub fn narrative(&self) -> &EmbeddingVec {
@@ -53,6 +56,9 @@ impl SpecificSituationEmbedding {
     pub fn context(&self) -> &ContextEmbedding {
         &self.context
     }
+    pub fn time_span(&self) -> &DateTime<Utc> {
+        &self.time_span
+    }
 }
 impl Embeddable for SpecificSituation {
     type EmbeddingGen = SpecificSituationEmbedding;
@@ -66,6 +72,7 @@ impl Embeddable for SpecificSituation {
         Ok(SpecificSituationEmbedding {
             narrative: narrative_vec,
             context: context_vec,
+            time_span: *self.get_time_span(),
         })
     }
     fn embed_and_fuse(
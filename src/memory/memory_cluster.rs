@@ -1,19 +1,26 @@
 use chrono::{DateTime, Utc};
+use ordered_float::OrderedFloat;
 use petgraph::prelude::{EdgeIndex, NodeIndex, StableDiGraph};
 use petgraph::visit::EdgeRef;
 use petgraph::{Direction, Undirected};
-use rayon::prelude::IntoParallelIterator;
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, json};
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fmt::{Debug, Display};
 use std::sync::Arc;
 use thiserror::Error;
 use uuid::Uuid;
 
-use crate::memory::embedding::note::{EmbeddedMemoryNote, MemoryEmbedding};
-use crate::memory::embedding::{Embeddable, EmbeddingModel, EmbeddingVec};
-use crate::memory::memory_links::{LinkId, MemoryLinkType};
+use crate::memory::algo::hnsw::{EmbeddingFacet, MemoryAnnIndex};
+use crate::memory::embedding::note::{EmbeddedMemoryNote, EmbeddedMemoryNoteRef, MemoryEmbedding};
+use crate::memory::embedding::{
+    Embeddable, EmbeddingCalcError, EmbeddingGenError, EmbeddingModel, EmbeddingVec,
+};
+use crate::memory::memory_links::{LinkId, LinkKind, MemoryLinkType, RejectedLink};
+use crate::memory::query::filter::MemoryFilter;
+use crate::memory::record::{Record, UserFeedback};
 
 use super::memory_note::MemoryId;
 
@@ -58,6 +65,9 @@ pub struct MemoryCluster {
     link_id_to_index: HashMap<LinkId, EdgeIndex>,
     incompletely_linked_note: HashMap<MemoryId, Vec<(NodeIndex, MemoryLink)>>, //目标节点的uuid，Vec<(源节点的index，关系)>，TODO：或许这里可以直接用GraphMemoryLink减少不必要的构造
     embedding_store: HashMap<MemoryId, MemoryEmbedding>, //由于link储存在source节点，source节点不在图中，link则不可知，因此source节点通常总是有效
+    ann_index: MemoryAnnIndex,                           //与embedding_store保持同步的近似最近邻索引
+    records: HashMap<MemoryId, Record>,                  //记忆的访问与反馈记录，按需创建
+    unloaded_neighbors: HashMap<MemoryId, HashSet<MemoryId>>, //软标记：节点在长期记忆中尚未加载的邻居，已加载的邻居在读取时忽略
 }
 impl MemoryCluster {
     pub fn new() -> Self {
@@ -67,6 +77,9 @@ impl MemoryCluster {
             link_id_to_index: HashMap::new(),
             incompletely_linked_note: HashMap::new(),
             embedding_store: HashMap::new(),
+            ann_index: MemoryAnnIndex::new(),
+            records: HashMap::new(),
+            unloaded_neighbors: HashMap::new(),
         }
     }
     // 获取内部图的不可变引用
@@ -83,28 +96,44 @@ impl MemoryCluster {
         self.link_id_to_index.contains_key(&link_id)
     }
     fn add_embeddings(&mut self, node_id: MemoryId, embeddings: MemoryEmbedding) {
+        self.ann_index.insert(node_id, &embeddings);
         self.embedding_store.insert(node_id, embeddings);
     }
-    pub fn add_single_node(&mut self, embed_node: EmbeddedMemoryNote) {
+    /// 加入单个节点并建立它的连接（包括以它为目标的待连接边）
+    ///
+    /// 不符合连接模式的边被丢弃并在Err中给出，节点与其余的边仍然会被加入
+    pub fn add_single_node(
+        &mut self,
+        embed_node: EmbeddedMemoryNote,
+    ) -> Result<(), Vec<RejectedLink>> {
         let (id, links) = (embed_node.note().id(), embed_node.note().links().to_owned());
-        self.merge_node(embed_node);
+        let (_, mut rejected) = self.merge_node(embed_node);
         if let Some(&node_index) = self.mem_id_to_index.get(&id) {
-            self.merge_edges(node_index, links)
+            rejected.extend(self.merge_edges(node_index, links));
+        }
+        if rejected.is_empty() {
+            Ok(())
+        } else {
+            Err(rejected)
         }
     }
-    /// 在直接修改节点的连接后，必须调用此方法
-    pub fn refresh_node(&mut self, node: &MemoryId) {
+    /// 在直接修改节点的连接后，必须调用此方法，返回不符合连接模式而被丢弃的边
+    pub fn refresh_node(&mut self, node: &MemoryId) -> Vec<RejectedLink> {
         if let Some(node_index) = self.mem_id_to_index.get(node) {
             if let Some(node) = self.graph.node_weight(*node_index) {
-                self.merge_edges(*node_index, node.links().to_owned());
+                return self.merge_edges(*node_index, node.links().to_owned());
             }
         }
+        Vec::new()
     }
     /// 删除单个节点，返回被删除的节点，并清理冗余项目，添加pending边
     pub fn remove_single_node(&mut self, node_id: MemoryId) -> Option<MemoryNote> {
         //TODO: test it
         if let Some(idx) = self.mem_id_to_index.remove(&node_id) {
             self.embedding_store.remove(&node_id);
+            self.ann_index.remove(node_id);
+            self.records.remove(&node_id);
+            self.unloaded_neighbors.remove(&node_id);
             //清理所有pending的边中，源节点是node_id的项
             self.incompletely_linked_note
                 .values_mut()
@@ -130,6 +159,18 @@ impl MemoryCluster {
 
             self.incompletely_linked_note
                 .insert(node_id, incoming_neighbors);
+            //被删除节点指向的节点失去了一个已加载的邻居
+            let outgoing_neighbors = self
+                .graph
+                .neighbors_directed(idx, Direction::Outgoing)
+                .filter_map(|index| self.graph.node_weight(index).map(|note| note.id()))
+                .collect::<Vec<_>>();
+            for neighbor in outgoing_neighbors {
+                self.unloaded_neighbors
+                    .entry(neighbor)
+                    .or_default()
+                    .insert(node_id);
+            }
             self.graph.remove_node(idx)
         } else {
             None
@@ -140,9 +181,191 @@ impl MemoryCluster {
             .get(&node_id)
             .and_then(|&index| self.graph.node_weight(index))
     }
+    pub fn node_index(&self, node_id: MemoryId) -> Option<NodeIndex> {
+        self.mem_id_to_index
+            .get(&node_id)
+            .copied()
+            .filter(|&index| self.graph.contains_node(index))
+    }
+    pub fn get_edge(&self, link_id: LinkId) -> Option<&GraphMemoryLink> {
+        self.link_id_to_index
+            .get(&link_id)
+            .and_then(|&index| self.graph.edge_weight(index))
+    }
     pub fn get_embedding(&self, node_id: MemoryId) -> Option<&MemoryEmbedding> {
         self.embedding_store.get(&node_id)
     }
+    pub fn record(&self, node_id: MemoryId) -> Option<&Record> {
+        self.records.get(&node_id)
+    }
+    /// 记录一次提取，同时更新节点的提取次数与访问时间
+    pub fn record_retrieval(&mut self, node_id: MemoryId) -> bool {
+        let Some(note) = self.get_node_mut(node_id) else {
+            return false;
+        };
+        note.retrieval_increment();
+        self.records
+            .entry(node_id)
+            .or_insert_with(|| Record::new(node_id))
+            .record_retrieval();
+        true
+    }
+    pub fn add_feedback(&mut self, node_id: MemoryId, feedback: UserFeedback) -> bool {
+        if !self.contains_node(node_id) {
+            return false;
+        }
+        self.records
+            .entry(node_id)
+            .or_insert_with(|| Record::new(node_id))
+            .add_feedback(feedback);
+        true
+    }
+    pub fn ann_index(&self) -> &MemoryAnnIndex {
+        &self.ann_index
+    }
+    /// 在指定的嵌入分量上做近似最近邻搜索，返回至多k个(MemoryId, 余弦相似度)
+    pub fn search_similar(
+        &self,
+        facet: EmbeddingFacet,
+        query: &EmbeddingVec,
+        k: usize,
+    ) -> Vec<(MemoryId, f32)> {
+        self.ann_index.search(facet, query, k)
+    }
+    /// 单个无结构查询，见batch_query
+    pub fn query(
+        &self,
+        query: &LTQueryType,
+        model: &dyn EmbeddingModel,
+        k: usize,
+    ) -> Result<Vec<(MemoryId, f32)>, ClusterError> {
+        let batch = match query {
+            LTQueryType::Text(text) => BatchLTQueryType::Text(vec![text.clone()]),
+            LTQueryType::Id(id) => BatchLTQueryType::Id(vec![*id]),
+            LTQueryType::Embedding(vec) => BatchLTQueryType::Embedding(vec![vec.clone()]),
+        };
+        Ok(self
+            .batch_query(&batch, model, k)?
+            .into_iter()
+            .next()
+            .unwrap_or_default())
+    }
+    /// 批量无结构查询，按查询顺序返回每个查询的前k个(MemoryId, 余弦相似度)
+    ///
+    /// 文本在一次infer_batch中嵌入；MemoryId以该记忆的内容向量（无内容时为tag）作为查询向量。
+    /// 所有查询向量只遍历一次存储，计算与每条记忆关键向量（见MemoryEmbedding::key_vectors）的
+    /// 相似度矩阵，记忆的分数取其各关键向量中的最大值。
+    pub fn batch_query(
+        &self,
+        query: &BatchLTQueryType,
+        model: &dyn EmbeddingModel,
+        k: usize,
+    ) -> Result<Vec<Vec<(MemoryId, f32)>>, ClusterError> {
+        let queries = match query {
+            BatchLTQueryType::Text(texts) => {
+                if texts.is_empty() {
+                    Vec::new()
+                } else {
+                    model.infer_batch(&texts.iter().map(String::as_str).collect::<Vec<_>>())?
+                }
+            }
+            BatchLTQueryType::Id(ids) => ids
+                .iter()
+                .map(|&id| {
+                    self.embedding_store
+                        .get(&id)
+                        .map(|embedding| embedding.primary_vector().clone())
+                        .ok_or(ClusterError::NodeNotFound(id))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            BatchLTQueryType::Embedding(vecs) => vecs.clone(),
+        };
+        if queries.is_empty() {
+            return Ok(Vec::new());
+        }
+        let queries = queries
+            .iter()
+            .map(Self::unit_or_zero)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let matrix = self
+            .embedding_store
+            .par_iter()
+            .filter(|(id, _)| self.contains_node(**id))
+            .map(|(&id, embedding)| {
+                let keys = embedding
+                    .key_vectors()
+                    .into_iter()
+                    .map(Self::unit_or_zero)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let row = queries
+                    .iter()
+                    .map(|query| {
+                        keys.iter().try_fold(f32::NEG_INFINITY, |best, key| {
+                            Ok::<_, EmbeddingCalcError>(best.max(query.dot(key)?))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((id, row))
+            })
+            .collect::<Result<Vec<_>, EmbeddingCalcError>>()?;
+
+        Ok((0..queries.len())
+            .map(|column| {
+                let mut ranked = matrix
+                    .iter()
+                    .map(|(id, row)| (*id, row[column]))
+                    .collect::<Vec<_>>();
+                ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                ranked.truncate(k);
+                ranked
+            })
+            .collect())
+    }
+    //单位化，零向量保持为零向量（与任何向量的相似度为0）
+    fn unit_or_zero(vec: &EmbeddingVec) -> Result<EmbeddingVec, EmbeddingCalcError> {
+        let norm = vec.norm()?;
+        if norm > 0.0 {
+            vec.normalize()
+        } else {
+            Ok(vec.clone())
+        }
+    }
+    /// 遍历所有同时具有节点和嵌入向量的记忆
+    pub fn embedded_notes(&self) -> impl Iterator<Item = EmbeddedMemoryNoteRef<'_>> {
+        self.graph.node_weights().filter_map(|note| {
+            self.embedding_store
+                .get(&note.id())
+                .map(|embedding| EmbeddedMemoryNoteRef {
+                    note,
+                    embedding,
+                    record: self.records.get(&note.id()),
+                })
+        })
+    }
+    /// 满足过滤表达式的记忆
+    pub fn filter_notes<'a>(
+        &'a self,
+        filter: &'a MemoryFilter,
+    ) -> impl Iterator<Item = &'a MemoryNote> + 'a {
+        self.graph
+            .node_weights()
+            .filter(|note| filter.matches_in(self, note))
+    }
+    /// a与b之间任一方向的全部边，任一节点不在记忆簇中时为空
+    pub fn links_between(
+        &self,
+        a: MemoryId,
+        b: MemoryId,
+    ) -> impl Iterator<Item = &GraphMemoryLink> + '_ {
+        let indices = self.node_index(a).zip(self.node_index(b));
+        indices.into_iter().flat_map(move |(a, b)| {
+            self.graph
+                .edges_connecting(a, b)
+                .chain(self.graph.edges_connecting(b, a))
+                .map(|edge| edge.weight())
+        })
+    }
     pub fn get_node_mut(&mut self, node_id: MemoryId) -> Option<&mut MemoryNote> {
         self.mem_id_to_index
             .get(&node_id)
@@ -182,13 +405,14 @@ impl MemoryCluster {
             None
         }
     }
-    pub fn merge(&mut self, other: Vec<EmbeddedMemoryNote>) {
+    /// 加入一批节点并建立它们的连接，返回不符合连接模式而被丢弃的边
+    pub fn merge(&mut self, other: Vec<EmbeddedMemoryNote>) -> Vec<RejectedLink> {
         let to_merged_edge = other
             .iter()
             .map(|x| (x.note().id(), x.note().links().to_owned()))
             .collect::<Vec<_>>();
 
-        self.merge_nodes(other);
+        let mut rejected = self.merge_nodes(other);
         let to_merged_edge = to_merged_edge
             .into_iter()
             .filter_map(|(id, links)| {
@@ -199,10 +423,183 @@ impl MemoryCluster {
                 }
             })
             .collect::<Vec<_>>();
-        self.merge_batch_edges(to_merged_edge);
+        rejected.extend(self.merge_batch_edges(to_merged_edge));
+        rejected
+    }
+    /// 将另一个记忆簇并入当前记忆簇，例如把新巩固的一批记忆并入工作记忆
+    ///
+    /// 只在other中的节点连同嵌入一起加入；两侧都有但内容不同的节点按policy决定保留哪一侧，
+    /// 被保留一侧的连接决定该节点的出边，替换后指入它的边按新内容重新检查连接模式；
+    /// 替换时并入一侧没有嵌入则删除原嵌入。所有并入节点的连接在全部节点加入后重新建立，
+    /// 因此任意一侧中目标缺失的悬挂边，只要目标出现在另一侧就会被连上。访问记录总是合并。
+    pub fn merge_cluster(&mut self, other: MemoryCluster, policy: MergePolicy) -> MergeReport {
+        let MemoryCluster {
+            mut graph,
+            mut embedding_store,
+            records,
+            unloaded_neighbors,
+            ..
+        } = other;
+        let mut report = MergeReport::default();
+        let mut merged_links = Vec::new();
+        for index in graph.node_indices().collect::<Vec<_>>() {
+            let Some(note) = graph.remove_node(index) else {
+                continue;
+            };
+            let id = note.id();
+            let embedding = embedding_store.remove(&id);
+            let links = note.links().to_owned();
+            match self.node_index(id) {
+                None => {
+                    let Some(embedding) = embedding else {
+                        log::warn!("Skipped merging node {id} without embedding");
+                        continue;
+                    };
+                    let (_, rejected) = self.add_new_node((note, embedding));
+                    report.rejected.extend(rejected);
+                    report.added.push(id);
+                }
+                //内容相同的节点及其连接都已存在
+                Some(existing) if self.graph[existing] == note => continue,
+                Some(existing) => {
+                    if !policy.prefers_incoming(&self.graph[existing], &note) {
+                        report.kept.push(id);
+                        continue;
+                    }
+                    report
+                        .rejected
+                        .extend(self.replace_node(existing, note, embedding));
+                    report.replaced.push(id);
+                }
+            }
+            merged_links.push((id, links));
+        }
+        for (id, links) in merged_links {
+            if let Some(index) = self.node_index(id) {
+                report.rejected.extend(self.merge_edges(index, links));
+            }
+        }
+        for (id, record) in records {
+            if !self.contains_node(id) {
+                continue;
+            }
+            match self.records.get_mut(&id) {
+                Some(existing) => existing.merge(record),
+                None => {
+                    self.records.insert(id, record);
+                }
+            }
+        }
+        for (id, neighbors) in unloaded_neighbors {
+            self.mark_unloaded_neighbors(id, neighbors);
+        }
+        report
+    }
+
+    /// 标记节点在长期记忆中还有尚未加载的邻居，节点不在记忆簇中时返回false
+    pub fn mark_unloaded_neighbors(
+        &mut self,
+        node_id: MemoryId,
+        neighbors: impl IntoIterator<Item = MemoryId>,
+    ) -> bool {
+        if !self.contains_node(node_id) {
+            return false;
+        }
+        let marked = self.unloaded_neighbors.entry(node_id).or_default();
+        marked.extend(
+            neighbors
+                .into_iter()
+                .filter(|&neighbor| neighbor != node_id),
+        );
+        true
+    }
+    /// 节点尚未加载的邻居：软标记的邻居与悬挂出边的目标中，当前不在记忆簇中的节点
+    pub fn unloaded_neighbors(&self, node_id: MemoryId) -> HashSet<MemoryId> {
+        let Some(index) = self.node_index(node_id) else {
+            return HashSet::new();
+        };
+        let pending = self
+            .incompletely_linked_note
+            .iter()
+            .filter(|(_, pending)| pending.iter().any(|(source, _)| *source == index))
+            .map(|(&target, _)| target);
+        self.unloaded_neighbors
+            .get(&node_id)
+            .into_iter()
+            .flatten()
+            .copied()
+            .chain(pending)
+            .filter(|&neighbor| !self.contains_node(neighbor))
+            .collect()
+    }
+    /// 节点是否处于已加载子图的边界，即在长期记忆中还有尚未加载的邻居
+    pub fn is_frontier(&self, node_id: MemoryId) -> bool {
+        !self.unloaded_neighbors(node_id).is_empty()
+    }
+    /// 所有边界节点，按MemoryId排序
+    pub fn frontier(&self) -> Vec<MemoryId> {
+        let mut frontier = self
+            .unloaded_neighbors
+            .keys()
+            .copied()
+            .chain(
+                self.incompletely_linked_note
+                    .values()
+                    .flatten()
+                    .filter_map(|(source, _)| self.graph.node_weight(*source))
+                    .map(|note| note.id()),
+            )
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter(|&id| self.is_frontier(id))
+            .collect::<Vec<_>>();
+        frontier.sort();
+        frontier
     }
-    pub fn merge_cluster(&mut self, _other: MemoryCluster) {
-        todo!()
+    /// 懒加载：检索策略到达边界节点时调用，从长期记忆中加载这些节点尚未加载的邻居
+    ///
+    /// 新加载的节点连同其悬挂边一并并入，加载器给出的邻居成为新的软标记，
+    /// 因此边界随加载向外推移。nodes中不是边界的节点会被忽略，返回新加入的节点。
+    pub fn expand_frontier(
+        &mut self,
+        nodes: &[MemoryId],
+        loader: &dyn FrontierLoader,
+    ) -> Result<Vec<MemoryId>, ClusterError> {
+        let mut wanted = nodes
+            .iter()
+            .flat_map(|&id| self.unloaded_neighbors(id))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        if wanted.is_empty() {
+            return Ok(Vec::new());
+        }
+        wanted.sort();
+        let loaded = loader.load(&wanted).map_err(ClusterError::Load)?;
+
+        let mut added = Vec::new();
+        let mut marks = Vec::new();
+        let mut notes = Vec::new();
+        for LoadedMemory { note, neighbors } in loaded {
+            let id = note.note().id();
+            if !self.contains_node(id) {
+                added.push(id);
+            }
+            marks.push((id, neighbors));
+            notes.push(note);
+        }
+        for rejected in self.merge(notes) {
+            log::warn!("Loaded memory has an invalid link: {rejected}");
+        }
+        for (id, neighbors) in marks {
+            self.mark_unloaded_neighbors(id, neighbors);
+        }
+        //已加载的邻居不再需要标记
+        self.unloaded_neighbors.retain(|_, neighbors| {
+            neighbors.retain(|neighbor| !added.contains(neighbor));
+            !neighbors.is_empty()
+        });
+        Ok(added)
     }
     pub fn sub_cluster(
         &self,
@@ -215,7 +612,123 @@ impl MemoryCluster {
             super_cluster: &self,
         }
     }
-    fn merge_node(&mut self, embed_node: EmbeddedMemoryNote) -> NodeIndex {
+    /// 以seeds为中心提取k跳邻域，即“将k跳内的邻居加载进工作记忆”时要加载的子图
+    ///
+    /// 只沿满足neighborhood条件的边扩展，不在cluster中的种子会被忽略。
+    /// 子图的边为邻域内节点关联的全部满足连接种类条件的边，包括另一端在邻域外的边。
+    pub fn neighborhood(
+        &self,
+        seeds: &[MemoryId],
+        neighborhood: &Neighborhood,
+    ) -> MemorySubCluster<'_> {
+        let mut node_ids = HashSet::new();
+        let mut frontier = seeds
+            .iter()
+            .filter_map(|&id| self.node_index(id).filter(|_| node_ids.insert(id)))
+            .collect::<Vec<_>>();
+        for _ in 0..neighborhood.hops {
+            let mut next = Vec::new();
+            for &index in &frontier {
+                for direction in neighborhood.directions() {
+                    for edge in self.graph.edges_directed(index, direction) {
+                        if !neighborhood.admits(edge.weight().link_type()) {
+                            continue;
+                        }
+                        let neighbor = match direction {
+                            Direction::Outgoing => edge.target(),
+                            Direction::Incoming => edge.source(),
+                        };
+                        if node_ids.insert(self.graph[neighbor].id()) {
+                            next.push(neighbor);
+                        }
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        let edge_ids = node_ids
+            .iter()
+            .filter_map(|&id| self.node_index(id))
+            .flat_map(|index| {
+                self.graph
+                    .edges_directed(index, Direction::Incoming)
+                    .chain(self.graph.edges_directed(index, Direction::Outgoing))
+            })
+            .filter(|edge| neighborhood.admits(edge.weight().link_type()))
+            .map(|edge| edge.weight().id())
+            .collect::<HashSet<_>>();
+        self.sub_cluster(node_ids, edge_ids)
+    }
+    /// 找出每个种子节点到每个目标节点的最佳激活路径，通常用于解释PPR等联想检索的结果
+    ///
+    /// 路径代价为各边-ln(strength)之和，即最大化路径上连接强度的乘积，强度大于1的按1计，
+    /// 强度为0的边不可通行。不可达的(种子, 目标)对不会出现在结果中，种子到自身的路径为空。
+    pub fn activation_paths(
+        &self,
+        seeds: &[MemoryId],
+        targets: &[MemoryId],
+    ) -> Vec<ActivationPath> {
+        let mut paths = Vec::new();
+        for &seed in seeds {
+            let Some(source) = self.node_index(seed) else {
+                continue;
+            };
+            let predecessors = self.strongest_path_tree(source);
+            for &target in targets {
+                let Some(mut current) = self.node_index(target) else {
+                    continue;
+                };
+                let mut links = Vec::new();
+                while current != source {
+                    match predecessors.get(&current) {
+                        Some(&(prev, link_id)) => {
+                            links.push(link_id);
+                            current = prev;
+                        }
+                        None => break,
+                    }
+                }
+                if current == source {
+                    links.reverse();
+                    paths.push(ActivationPath {
+                        seed,
+                        target,
+                        links,
+                    });
+                }
+            }
+        }
+        paths
+    }
+    //以source为起点的Dijkstra，返回最短路径树中每个节点的(前驱节点, 经过的边)
+    fn strongest_path_tree(&self, source: NodeIndex) -> HashMap<NodeIndex, (NodeIndex, LinkId)> {
+        let mut cost = HashMap::from([(source, 0.0f32)]);
+        let mut predecessors = HashMap::new();
+        let mut heap = BinaryHeap::from([Reverse((OrderedFloat(0.0f32), source))]);
+        while let Some(Reverse((OrderedFloat(node_cost), node))) = heap.pop() {
+            if cost.get(&node).is_some_and(|&c| node_cost > c) {
+                continue;
+            }
+            for edge in self.graph.edges_directed(node, Direction::Outgoing) {
+                let strength = edge.weight().link_type().strength().min(1.0);
+                if strength <= 0.0 {
+                    continue;
+                }
+                let next_cost = node_cost - strength.ln();
+                let target = edge.target();
+                if cost.get(&target).is_none_or(|&c| next_cost < c) {
+                    cost.insert(target, next_cost);
+                    predecessors.insert(target, (node, edge.weight().id()));
+                    heap.push(Reverse((OrderedFloat(next_cost), target)));
+                }
+            }
+        }
+        predecessors
+    }
+    fn merge_node(&mut self, embed_node: EmbeddedMemoryNote) -> (NodeIndex, Vec<RejectedLink>) {
         let node_id = embed_node.note().id();
 
         match self.mem_id_to_index.get(&node_id) {
@@ -224,7 +737,7 @@ impl MemoryCluster {
                 if let Some(existing_node) = self.graph.node_weight_mut(index) {
                     existing_node.retrieval_increment();
                 }
-                index
+                (index, Vec::new())
             }
             _ => {
                 // 节点不存在或索引无效
@@ -232,21 +745,79 @@ impl MemoryCluster {
             }
         }
     }
-    fn add_new_node(&mut self, embed_node: (MemoryNote, MemoryEmbedding)) -> NodeIndex {
+    //用新内容替换已有节点，删除原有的全部出边（包括悬挂边），由调用方按新内容的连接重新建立；
+    //指入的边按新内容重新检查连接模式，不合法的边被删除并返回。嵌入缺失时删除原嵌入，
+    //避免旧内容的向量继续代表新内容
+    fn replace_node(
+        &mut self,
+        index: NodeIndex,
+        note: MemoryNote,
+        embedding: Option<MemoryEmbedding>,
+    ) -> Vec<RejectedLink> {
+        let id = note.id();
+        let outgoing = self
+            .graph
+            .edges_directed(index, Direction::Outgoing)
+            .map(|edge| (edge.id(), edge.weight().id()))
+            .collect::<Vec<_>>();
+        for (edge_index, link_id) in outgoing {
+            self.graph.remove_edge(edge_index);
+            self.link_id_to_index.remove(&link_id);
+        }
+        self.incompletely_linked_note
+            .values_mut()
+            .for_each(|pending| pending.retain(|(source, _)| *source != index));
+        self.graph[index] = note;
+
+        let mut rejected = Vec::new();
+        let incoming = self
+            .graph
+            .edges_directed(index, Direction::Incoming)
+            .filter_map(|edge| {
+                let source = &self.graph[edge.source()];
+                let link = edge.weight();
+                link.link_type()
+                    .validate(source, &self.graph[index])
+                    .err()
+                    .map(|err| (edge.id(), RejectedLink::new(link.id(), err)))
+            })
+            .collect::<Vec<_>>();
+        for (edge_index, link) in incoming {
+            self.graph.remove_edge(edge_index);
+            self.link_id_to_index.remove(&link.link());
+            rejected.push(link);
+        }
+
+        match embedding {
+            Some(embedding) => self.add_embeddings(id, embedding),
+            None => {
+                self.embedding_store.remove(&id);
+                self.ann_index.remove(id);
+            }
+        }
+        rejected
+    }
+    //返回新节点的索引，以及以它为目标的待连接边中被丢弃的边
+    fn add_new_node(
+        &mut self,
+        embed_node: (MemoryNote, MemoryEmbedding),
+    ) -> (NodeIndex, Vec<RejectedLink>) {
         let node_id = embed_node.0.id();
 
         let index = self.graph.add_node(embed_node.0);
+        self.add_embeddings(node_id, embed_node.1);
 
         // 清理可能存在的无效索引
         //self.id_to_index.remove(&node_id);
         self.mem_id_to_index.insert(node_id.clone(), index);
 
         // 处理悬挂边
-        self.process_pending_edges(&node_id);
+        let rejected = self.process_pending_edges(&node_id);
 
-        index
+        (index, rejected)
     }
-    fn process_pending_edges(&mut self, node_id: &MemoryId) {
+    fn process_pending_edges(&mut self, node_id: &MemoryId) -> Vec<RejectedLink> {
+        let mut rejected = Vec::new();
         if let Some(pending_edges) = self.incompletely_linked_note.remove(node_id) {
             for (source_index, edge) in pending_edges {
                 if !self.graph.contains_node(source_index) {
@@ -254,30 +825,35 @@ impl MemoryCluster {
                     // 处理源节点丢失的情况
                     continue;
                 }
-                self.merge_edge(source_index, edge);
+                rejected.extend(self.merge_edge(source_index, edge).err());
             }
         }
+        rejected
     }
-    fn merge_nodes(&mut self, nodes: Vec<EmbeddedMemoryNote>) -> Vec<NodeIndex> {
+    //各节点以自身为目标的待连接边中被丢弃的边
+    fn merge_nodes(&mut self, nodes: Vec<EmbeddedMemoryNote>) -> Vec<RejectedLink> {
         nodes
             .into_iter()
-            .map(|x| self.merge_node(x))
+            .flat_map(|x| self.merge_node(x).1)
             .collect::<Vec<_>>()
     }
-    fn merge_edges(&mut self, source: NodeIndex, edges: Vec<MemoryLink>) {
-        for edge in edges {
-            self.merge_edge(source, edge);
-        }
+    fn merge_edges(&mut self, source: NodeIndex, edges: Vec<MemoryLink>) -> Vec<RejectedLink> {
+        edges
+            .into_iter()
+            .filter_map(|edge| self.merge_edge(source, edge).err())
+            .collect()
     }
-    fn merge_batch_edges(&mut self, edges: Vec<(NodeIndex, Vec<MemoryLink>)>) {
-        for (source, edges) in edges {
-            self.merge_edges(source, edges);
-        }
+    fn merge_batch_edges(&mut self, edges: Vec<(NodeIndex, Vec<MemoryLink>)>) -> Vec<RejectedLink> {
+        edges
+            .into_iter()
+            .flat_map(|(source, edges)| self.merge_edges(source, edges))
+            .collect()
     }
-    fn merge_edge(&mut self, source: NodeIndex, edge: MemoryLink) {
+    //只有不符合连接模式的边返回Err，源节点无效或目标缺失（转为待连接边）都不算错误
+    fn merge_edge(&mut self, source: NodeIndex, edge: MemoryLink) -> Result<(), RejectedLink> {
         if !self.graph.contains_node(source) {
             log::warn!("Attempted to add edge from invalid source node");
-            return;
+            return Ok(());
         }
 
         let target_id = edge.to();
@@ -286,8 +862,12 @@ impl MemoryCluster {
             if !self.graph.contains_node(target_index) {
                 self.mem_id_to_index.remove(&target_id);
                 self.add_pending_edge(target_id, (source, edge));
-                return;
+                return Ok(());
             }
+            //不符合连接模式的边直接丢弃，也不再作为待连接边保留
+            edge.link_type()
+                .validate(&self.graph[source], &self.graph[target_index])
+                .map_err(|err| RejectedLink::new(edge_id, err))?;
             if !self.has_edge(edge.id()) {
                 let edge_index =
                     self.graph
@@ -297,6 +877,7 @@ impl MemoryCluster {
         } else {
             self.add_pending_edge(target_id, (source, edge))
         }
+        Ok(())
     }
     fn add_pending_edge(&mut self, target_id: MemoryId, edge: (NodeIndex, MemoryLink)) {
         self.incompletely_linked_note
@@ -316,6 +897,169 @@ impl Debug for MemoryCluster {
     }
 }
 
+//从种子节点到目标节点的激活路径，links按经过顺序排列
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivationPath {
+    seed: MemoryId,
+    target: MemoryId,
+    links: Vec<LinkId>,
+}
+impl ActivationPath {
+    pub fn seed(&self) -> MemoryId {
+        self.seed
+    }
+    pub fn target(&self) -> MemoryId {
+        self.target
+    }
+    pub fn links(&self) -> &[LinkId] {
+        &self.links
+    }
+    pub fn into_links(self) -> Vec<LinkId> {
+        self.links
+    }
+}
+
+//k跳邻域的提取条件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighborhood {
+    hops: usize,
+    direction: Option<Direction>,  //只沿该方向扩展，None表示双向
+    link_kinds: HashSet<LinkKind>, //只沿这些种类的边扩展，为空表示不限
+}
+impl Neighborhood {
+    pub fn new(hops: usize) -> Self {
+        Self {
+            hops,
+            direction: None,
+            link_kinds: HashSet::new(),
+        }
+    }
+    pub fn with_direction(mut self, direction: Direction) -> Self {
+        self.direction = Some(direction);
+        self
+    }
+    pub fn with_link_kind(mut self, kind: LinkKind) -> Self {
+        self.link_kinds.insert(kind);
+        self
+    }
+    pub fn hops(&self) -> usize {
+        self.hops
+    }
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+    pub fn link_kinds(&self) -> &HashSet<LinkKind> {
+        &self.link_kinds
+    }
+    pub fn admits(&self, link_type: &MemoryLinkType) -> bool {
+        self.link_kinds.is_empty() || self.link_kinds.contains(&link_type.kind())
+    }
+    fn directions(&self) -> Vec<Direction> {
+        match self.direction {
+            Some(direction) => vec![direction],
+            None => vec![Direction::Outgoing, Direction::Incoming],
+        }
+    }
+}
+
+/// 从长期记忆中按需加载记忆的钩子，供MemoryCluster::expand_frontier调用
+pub trait FrontierLoader: Send + Sync {
+    /// 加载指定的记忆，找不到的记忆可以省略
+    fn load(&self, ids: &[MemoryId]) -> anyhow::Result<Vec<LoadedMemory>>;
+}
+
+//从长期记忆中加载的一条记忆，neighbors为它在长期记忆中的全部邻居（不论方向）
+#[derive(Debug, Clone)]
+pub struct LoadedMemory {
+    note: EmbeddedMemoryNote,
+    neighbors: Vec<MemoryId>,
+}
+impl LoadedMemory {
+    pub fn new(note: EmbeddedMemoryNote) -> Self {
+        Self {
+            note,
+            neighbors: Vec::new(),
+        }
+    }
+    pub fn with_neighbors(mut self, neighbors: impl Into<Vec<MemoryId>>) -> Self {
+        self.neighbors = neighbors.into();
+        self
+    }
+    pub fn note(&self) -> &EmbeddedMemoryNote {
+        &self.note
+    }
+    pub fn neighbors(&self) -> &[MemoryId] {
+        &self.neighbors
+    }
+}
+
+//完整的记忆簇（例如长期记忆的快照）可以直接作为加载器
+impl FrontierLoader for MemoryCluster {
+    fn load(&self, ids: &[MemoryId]) -> anyhow::Result<Vec<LoadedMemory>> {
+        Ok(ids
+            .iter()
+            .filter_map(|&id| {
+                let index = self.node_index(id)?;
+                let note = EmbeddedMemoryNote {
+                    embedding: self.get_embedding(id)?.clone(),
+                    note: self.graph[index].clone(),
+                };
+                let neighbors = self
+                    .graph
+                    .neighbors_undirected(index)
+                    .filter_map(|neighbor| self.graph.node_weight(neighbor).map(|n| n.id()))
+                    .collect::<Vec<_>>();
+                Some(LoadedMemory::new(note).with_neighbors(neighbors))
+            })
+            .collect())
+    }
+}
+
+//合并记忆簇时，两侧同一MemoryId的记忆内容不同时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    KeepExisting, //保留当前记忆簇中的内容
+    TakeIncoming, //使用并入记忆簇中的内容
+    KeepRecent,   //保留最后访问时间较新的一侧，相同时保留当前记忆簇中的内容
+}
+impl MergePolicy {
+    fn prefers_incoming(&self, existing: &MemoryNote, incoming: &MemoryNote) -> bool {
+        match self {
+            MergePolicy::KeepExisting => false,
+            MergePolicy::TakeIncoming => true,
+            MergePolicy::KeepRecent => {
+                incoming.last_accessed_time() > existing.last_accessed_time()
+            }
+        }
+    }
+}
+
+//一次记忆簇合并的结果，内容完全相同的节点不出现在任何一项中
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergeReport {
+    added: Vec<MemoryId>,        //新加入的节点
+    kept: Vec<MemoryId>,         //内容冲突，保留了当前记忆簇中的内容
+    replaced: Vec<MemoryId>,     //内容冲突，替换为并入记忆簇中的内容
+    rejected: Vec<RejectedLink>, //不符合连接模式而被丢弃的边
+}
+impl MergeReport {
+    pub fn added(&self) -> &[MemoryId] {
+        &self.added
+    }
+    pub fn kept(&self) -> &[MemoryId] {
+        &self.kept
+    }
+    pub fn replaced(&self) -> &[MemoryId] {
+        &self.replaced
+    }
+    pub fn rejected(&self) -> &[RejectedLink] {
+        &self.rejected
+    }
+    pub fn conflicts(&self) -> usize {
+        self.kept.len() + self.replaced.len()
+    }
+}
+
 pub enum LTQueryType {
     Text(String),
     Id(MemoryId),
@@ -324,7 +1068,7 @@ pub enum LTQueryType {
 pub enum BatchLTQueryType {
     Text(Vec<String>),
     Id(Vec<MemoryId>),
-    Embedding(Vec<EmbeddingVec>), // TODO: 待实现
+    Embedding(Vec<EmbeddingVec>),
 }
 impl BatchLTQueryType {
     pub fn as_text(&self) -> Option<&Vec<String>> {
@@ -339,9 +1083,14 @@ impl BatchLTQueryType {
             _ => None,
         }
     }
+    pub fn as_embedding(&self) -> Option<&Vec<EmbeddingVec>> {
+        match self {
+            BatchLTQueryType::Embedding(vecs) => Some(vecs),
+            _ => None,
+        }
+    }
 }
 
-//TODO: test it
 #[derive(Debug, Clone)]
 pub struct MemorySubCluster<'a> {
     node_ids: HashSet<MemoryId>,
@@ -373,9 +1122,97 @@ impl<'a> MemorySubCluster<'a> {
             Err(errors)
         }
     }
+    pub fn node_ids(&self) -> &HashSet<MemoryId> {
+        &self.node_ids
+    }
+    pub fn edge_ids(&self) -> &HashSet<LinkId> {
+        &self.edge_ids
+    }
     pub fn super_cluster(&self) -> &'a MemoryCluster {
         self.super_cluster
     }
+
+    /// 复制子图中的节点、嵌入与访问记录，得到不再借用原记忆簇的独立记忆簇
+    ///
+    /// 两端都在子图中且属于edge_ids的边被保留；一端在子图外的边被切断并记录在cut中，
+    /// 其中由子图内节点指出的边同时作为悬挂边保留在新记忆簇中，目标节点加入后会自动连上。
+    pub fn materialize(&self) -> MaterializedCluster {
+        let super_cluster = self.super_cluster;
+        let mut cluster = MemoryCluster::new();
+        let mut links = Vec::new();
+        let mut cut = Vec::new();
+        for &id in &self.node_ids {
+            let (Some(note), Some(embedding), Some(index)) = (
+                super_cluster.get_node(id),
+                super_cluster.get_embedding(id),
+                super_cluster.node_index(id),
+            ) else {
+                continue;
+            };
+            cluster.add_new_node((note.clone(), embedding.clone()));
+            if let Some(record) = super_cluster.record(id) {
+                cluster.records.insert(id, record.clone());
+            }
+
+            let mut outgoing = Vec::new();
+            for link in note.links() {
+                if !self.node_ids.contains(&link.to()) {
+                    cut.push(link.clone());
+                    outgoing.push(link.clone());
+                } else if self.edge_ids.contains(&link.id()) {
+                    outgoing.push(link.clone());
+                }
+            }
+            links.push((id, outgoing));
+            //由子图外节点指入的边，连接保存在源节点中
+            for edge in super_cluster
+                .graph
+                .edges_directed(index, Direction::Incoming)
+            {
+                let source = &super_cluster.graph[edge.source()];
+                if self.node_ids.contains(&source.id()) {
+                    continue;
+                }
+                if let Some(link) = source
+                    .links()
+                    .iter()
+                    .find(|link| link.id() == edge.weight().id())
+                {
+                    cut.push(link.clone());
+                }
+            }
+        }
+        for (id, outgoing) in links {
+            if let Some(index) = cluster.node_index(id) {
+                cluster.merge_edges(index, outgoing);
+            }
+        }
+        //子图外指入的边不能作为悬挂边保存，标记为未加载的邻居
+        for link in &cut {
+            if !self.node_ids.contains(&link.from()) {
+                cluster.mark_unloaded_neighbors(link.to(), [link.from()]);
+            }
+        }
+        MaterializedCluster { cluster, cut }
+    }
+}
+
+//由子图复制得到的独立记忆簇，可以交给在其他线程上运行的检索策略
+#[derive(Debug, Clone)]
+pub struct MaterializedCluster {
+    cluster: MemoryCluster,
+    cut: Vec<MemoryLink>, //一端在子图外而被切断的边
+}
+impl MaterializedCluster {
+    pub fn cluster(&self) -> &MemoryCluster {
+        &self.cluster
+    }
+    pub fn cut(&self) -> &[MemoryLink] {
+        &self.cut
+    }
+    pub fn into_parts(self) -> (MemoryCluster, Vec<MemoryLink>) {
+        (self.cluster, self.cut)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -384,6 +1221,14 @@ pub enum ClusterError {
     NodeNotContained(MemoryId),
     #[error("edge {0} not contained in Super.")]
     EdgeNotContained(LinkId),
+    #[error("memory {0} not found in cluster.")]
+    NodeNotFound(MemoryId),
+    #[error("Query embedding failed")]
+    EmbeddingGen(#[from] EmbeddingGenError),
+    #[error("Score calculation failed")]
+    EmbeddingCalc(#[from] EmbeddingCalcError),
+    #[error("Loading from long-term memory failed: {0}")]
+    Load(anyhow::Error),
     //PlaceHolder for now
 }
 //WARNING: Legacy Code below, maybe useful for later reuse
@@ -607,3 +1452,622 @@ mod test {
     //     println!("{:?}", cluster);
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::embedding::embedding_model::bge::BgeSmallZh;
+    use crate::memory::memory_links::LinkSchemaError;
+    use crate::memory::memory_links::cross_mem::{CrossMemLink, SemSituation};
+    use crate::memory::memory_links::proc_mem::{ProcMemLink, TrigToAction};
+    use crate::memory::memory_links::sem_mem::SemMemLink;
+    use crate::memory::memory_links::situation_mem::{AbstractToSpecific, SituationMemLink};
+    use crate::memory::memory_note::proc_mem::{Action, ActionType, ProcMemory};
+    use crate::memory::memory_note::sem_mem::{ConceptType, SemMemory};
+    use crate::memory::memory_note::situation_mem::{
+        AbstractSituation, Context, Environment, Location, SpecificSituation,
+    };
+    use crate::memory::memory_note::{MemoryKind, MemoryNoteBuilder, MemoryType};
+
+    fn semantic_cluster(model: &BgeSmallZh, contents: &[&str]) -> (MemoryCluster, Vec<MemoryId>) {
+        let notes = contents
+            .iter()
+            .map(|content| {
+                MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+                    content.to_string(),
+                    ConceptType::Entity,
+                    format!("{content}的描述"),
+                )))
+                .tags(vec![content.to_string()])
+                .build()
+                .unwrap()
+                .embed_and_fuse(model)
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let ids = notes.iter().map(|note| note.note().id()).collect();
+        let mut cluster = MemoryCluster::new();
+        cluster.merge(notes);
+        (cluster, ids)
+    }
+
+    #[test]
+    fn test_batch_query_by_text_id_and_embedding() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (cluster, ids) = semantic_cluster(&model, &["咖啡", "火锅", "钢琴"]);
+
+        let by_text = cluster
+            .batch_query(
+                &BatchLTQueryType::Text(vec!["咖啡".to_string(), "钢琴".to_string()]),
+                &model,
+                2,
+            )
+            .unwrap();
+        assert_eq!(by_text.len(), 2);
+        assert_eq!(by_text[0][0].0, ids[0]);
+        assert_eq!(by_text[1][0].0, ids[2]);
+        assert!(by_text.iter().all(|ranked| ranked.len() == 2));
+
+        //以记忆自身为查询时，自身总是排在第一位
+        let by_id = cluster
+            .batch_query(&BatchLTQueryType::Id(vec![ids[1]]), &model, 3)
+            .unwrap();
+        assert_eq!(by_id[0][0].0, ids[1]);
+        assert!((by_id[0][0].1 - 1.0).abs() < 1e-4);
+
+        let raw = model.infer_batch(&["火锅"]).unwrap();
+        let by_embedding = cluster
+            .query(&LTQueryType::Embedding(raw[0].clone()), &model, 1)
+            .unwrap();
+        assert_eq!(by_embedding, vec![by_text_top(&cluster, &model, "火锅")]);
+
+        assert!(matches!(
+            cluster.batch_query(&BatchLTQueryType::Id(vec![MemoryId::new()]), &model, 1),
+            Err(ClusterError::NodeNotFound(_))
+        ));
+        assert!(matches!(
+            cluster.batch_query(
+                &BatchLTQueryType::Embedding(vec![EmbeddingVec::new(vec![1.0, 0.0])]),
+                &model,
+                1
+            ),
+            Err(ClusterError::EmbeddingCalc(
+                EmbeddingCalcError::ShapeMismatch
+            ))
+        ));
+        assert!(
+            cluster
+                .batch_query(&BatchLTQueryType::Text(vec![]), &model, 1)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    fn by_text_top(cluster: &MemoryCluster, model: &BgeSmallZh, text: &str) -> (MemoryId, f32) {
+        cluster
+            .query(&LTQueryType::Text(text.to_string()), model, 1)
+            .unwrap()[0]
+    }
+
+    fn linked_note(
+        model: &BgeSmallZh,
+        id: MemoryId,
+        content: &str,
+        links: Vec<MemoryLink>,
+        last_accessed_time: DateTime<Utc>,
+    ) -> EmbeddedMemoryNote {
+        MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+            content.to_string(),
+            ConceptType::Entity,
+            format!("{content}的描述"),
+        )))
+        .id(id)
+        .tags(vec![content.to_string()])
+        .mem_links(links)
+        .last_accessed_time(last_accessed_time)
+        .build()
+        .unwrap()
+        .embed_and_fuse(model)
+        .unwrap()
+    }
+
+    #[test]
+    fn test_merge_cluster() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let related = || MemoryLinkType::Sem(SemMemLink::new("相关".to_string(), 1.0, 1.0));
+        let (a, b, shared) = (MemoryId::new(), MemoryId::new(), MemoryId::new());
+        let (a_to_b, b_to_a, shared_to_a) = (
+            MemoryLink::new(a, b, related()),
+            MemoryLink::new(b, a, related()),
+            MemoryLink::new(shared, a, related()),
+        );
+        let earlier = Utc::now() - chrono::TimeDelta::days(1);
+
+        //两侧各有一条目标在对方的悬挂边，shared在两侧的内容不同
+        let mut current = MemoryCluster::new();
+        current.merge(vec![
+            linked_note(&model, a, "咖啡", vec![a_to_b.clone()], earlier),
+            linked_note(&model, shared, "拿铁", vec![shared_to_a.clone()], earlier),
+        ]);
+        let mut incoming = MemoryCluster::new();
+        incoming.merge(vec![
+            linked_note(&model, b, "咖啡豆", vec![b_to_a.clone()], Utc::now()),
+            linked_note(&model, shared, "摩卡", vec![], Utc::now()),
+        ]);
+        incoming.record_retrieval(b);
+        assert!(!current.has_edge(a_to_b.id()));
+
+        let mut kept = current.clone();
+        let report = kept.merge_cluster(incoming.clone(), MergePolicy::KeepExisting);
+        assert_eq!(report.added(), &[b]);
+        assert_eq!(report.kept(), &[shared]);
+        assert!(report.replaced().is_empty());
+        assert!(report.rejected().is_empty());
+        assert!(kept.has_edge(a_to_b.id()));
+        assert!(kept.has_edge(b_to_a.id()));
+        assert!(kept.has_edge(shared_to_a.id()));
+        assert!(
+            kept.incompletely_linked_note
+                .values()
+                .all(|pending| pending.is_empty())
+        );
+        assert!(kept.ann_index().facet(EmbeddingFacet::Tag).contains(b));
+        assert_eq!(kept.record(b).unwrap().retrieval_count(), 1);
+        assert_eq!(by_text_top(&kept, &model, "拿铁").0, shared);
+
+        //替换为并入的内容时，旧内容的出边与嵌入一并被替换
+        for policy in [MergePolicy::TakeIncoming, MergePolicy::KeepRecent] {
+            let mut replaced = current.clone();
+            let report = replaced.merge_cluster(incoming.clone(), policy);
+            assert_eq!(report.replaced(), &[shared]);
+            assert_eq!(report.conflicts(), 1);
+            assert!(!replaced.has_edge(shared_to_a.id()));
+            assert!(replaced.has_edge(b_to_a.id()));
+            assert_eq!(by_text_top(&replaced, &model, "摩卡").0, shared);
+            assert_eq!(replaced.graph().node_count(), 3);
+            assert_eq!(replaced.embedding_store.len(), 3);
+        }
+
+        //内容相同的节点不算冲突
+        let mut same = current.clone();
+        let report = same.merge_cluster(current.clone(), MergePolicy::TakeIncoming);
+        assert_eq!(report, MergeReport::default());
+        assert_eq!(same.graph().edge_count(), 1);
+    }
+
+    #[test]
+    fn test_merge_cluster_revalidates_replaced_node() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (a, p) = (MemoryId::new(), MemoryId::new());
+        let recommend = MemoryLink::new(
+            a,
+            p,
+            MemoryLinkType::Proc(ProcMemLink::TrigToAction(TrigToAction::new(0.5))),
+        );
+        let mut current = MemoryCluster::new();
+        current.merge(vec![
+            linked_note(&model, a, "咖啡", vec![recommend.clone()], Utc::now()),
+            MemoryNoteBuilder::new(MemoryType::Procedure(ProcMemory::new(Action::new(
+                "推荐一款咖啡".to_string(),
+                ActionType::new_speak(),
+            ))))
+            .id(p)
+            .build()
+            .unwrap()
+            .embed_and_fuse(&model)
+            .unwrap(),
+        ]);
+        assert!(current.has_edge(recommend.id()));
+
+        //并入一侧把动作p换成了语义记忆，且没有嵌入
+        let mut incoming = MemoryCluster::new();
+        incoming.merge(vec![linked_note(&model, p, "拿铁", vec![], Utc::now())]);
+        incoming.embedding_store.remove(&p);
+        let report = current.merge_cluster(incoming, MergePolicy::TakeIncoming);
+        assert_eq!(report.replaced(), &[p]);
+        assert_eq!(
+            report
+                .rejected()
+                .iter()
+                .map(|r| r.link())
+                .collect::<Vec<_>>(),
+            vec![recommend.id()]
+        );
+        assert!(!current.has_edge(recommend.id()));
+        assert_eq!(current.graph().edge_count(), 0);
+        assert!(current.get_embedding(p).is_none());
+        assert!(!current.ann_index().facet(EmbeddingFacet::Tag).contains(p));
+        assert!(current.ann_index().facet(EmbeddingFacet::Tag).contains(a));
+    }
+
+    //语义链 a -> b -> c -> d，另有 b --TrigToAction--> p
+    fn chain_cluster(model: &BgeSmallZh) -> (MemoryCluster, [MemoryId; 5], [LinkId; 4]) {
+        let ids = [(); 5].map(|_| MemoryId::new());
+        let [a, b, c, d, p] = ids;
+        let related = |from, to| {
+            MemoryLink::new(
+                from,
+                to,
+                MemoryLinkType::Sem(SemMemLink::new("相关".to_string(), 1.0, 1.0)),
+            )
+        };
+        let links = [
+            related(a, b),
+            related(b, c),
+            related(c, d),
+            MemoryLink::new(
+                b,
+                p,
+                MemoryLinkType::Proc(ProcMemLink::TrigToAction(TrigToAction::new(0.5))),
+            ),
+        ];
+        let link_ids = links.clone().map(|link| link.id());
+        let [a_b, b_c, c_d, b_p] = links;
+        let mut notes = [("咖啡", a, vec![a_b]), ("咖啡豆", b, vec![b_c, b_p])]
+            .into_iter()
+            .chain([("烘焙", c, vec![c_d]), ("产地", d, vec![])])
+            .map(|(content, id, links)| linked_note(model, id, content, links, Utc::now()))
+            .collect::<Vec<_>>();
+        notes.push(
+            MemoryNoteBuilder::new(MemoryType::Procedure(ProcMemory::new(Action::new(
+                "推荐一款咖啡".to_string(),
+                ActionType::new_speak(),
+            ))))
+            .id(p)
+            .build()
+            .unwrap()
+            .embed_and_fuse(model)
+            .unwrap(),
+        );
+        let mut cluster = MemoryCluster::new();
+        cluster.merge(notes);
+        (cluster, ids, link_ids)
+    }
+
+    #[test]
+    fn test_neighborhood() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (cluster, [a, b, c, d, p], [a_b, b_c, c_d, b_p]) = chain_cluster(&model);
+
+        let both = cluster.neighborhood(&[b, MemoryId::new()], &Neighborhood::new(1));
+        assert_eq!(both.node_ids(), &HashSet::from([a, b, c, p]));
+        //邻域边缘节点c指向邻域外的边也属于子图
+        assert_eq!(both.edge_ids(), &HashSet::from([a_b, b_c, c_d, b_p]));
+
+        let outgoing = cluster.neighborhood(
+            &[b],
+            &Neighborhood::new(1).with_direction(Direction::Outgoing),
+        );
+        assert_eq!(outgoing.node_ids(), &HashSet::from([b, c, p]));
+
+        let semantic = cluster.neighborhood(
+            &[b],
+            &Neighborhood::new(5)
+                .with_direction(Direction::Outgoing)
+                .with_link_kind(LinkKind::Semantic),
+        );
+        assert_eq!(semantic.node_ids(), &HashSet::from([b, c, d]));
+        assert!(!semantic.edge_ids().contains(&b_p));
+
+        assert_eq!(
+            cluster.neighborhood(&[b], &Neighborhood::new(0)).node_ids(),
+            &HashSet::from([b])
+        );
+    }
+
+    #[test]
+    fn test_materialize_sub_cluster() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (mut cluster, [a, b, c, d, p], [a_b, b_c, c_d, b_p]) = chain_cluster(&model);
+        cluster.record_retrieval(b);
+
+        let materialized = cluster
+            .neighborhood(&[b], &Neighborhood::new(1))
+            .materialize();
+        let (owned, cut) = materialized.into_parts();
+        //独立的记忆簇可以移动到其他线程中使用
+        let owned = std::thread::spawn(move || owned).join().unwrap();
+        assert_eq!(owned.graph().node_count(), 4);
+        assert!([a_b, b_c, b_p].iter().all(|&link| owned.has_edge(link)));
+        assert!(!owned.has_edge(c_d));
+        assert!(
+            [a, b, c, p]
+                .iter()
+                .all(|&id| owned.get_embedding(id).is_some())
+        );
+        assert_eq!(owned.record(b).unwrap().retrieval_count(), 1);
+        assert_eq!(by_text_top(&owned, &model, "咖啡豆").0, b);
+
+        //被切断的出边作为悬挂边保留，目标节点加入后自动连上
+        assert_eq!(
+            cut.iter().map(|link| link.id()).collect::<Vec<_>>(),
+            vec![c_d]
+        );
+        assert!(owned.incompletely_linked_note.contains_key(&d));
+        let mut owned = owned;
+        owned.merge(vec![linked_note(&model, d, "产地", vec![], Utc::now())]);
+        assert!(owned.has_edge(c_d));
+
+        //由子图外节点指入的边同样记录为被切断
+        let single = cluster.sub_cluster([c], []).materialize();
+        let mut cut = single
+            .cut()
+            .iter()
+            .map(|link| link.id())
+            .collect::<Vec<_>>();
+        cut.sort();
+        let mut expected = vec![b_c, c_d];
+        expected.sort();
+        assert_eq!(cut, expected);
+        assert_eq!(single.cluster().graph().edge_count(), 0);
+    }
+
+    struct FailingLoader;
+    impl FrontierLoader for FailingLoader {
+        fn load(&self, _ids: &[MemoryId]) -> anyhow::Result<Vec<LoadedMemory>> {
+            anyhow::bail!("long-term storage unavailable")
+        }
+    }
+
+    #[test]
+    fn test_frontier_and_lazy_expansion() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (long_term, [a, b, c, d, p], [_, b_c, c_d, b_p]) = chain_cluster(&model);
+
+        //只加载了a的1跳邻域，b指向的c与p尚未加载
+        let (mut working, _) = long_term
+            .neighborhood(&[a], &Neighborhood::new(1))
+            .materialize()
+            .into_parts();
+        assert_eq!(working.frontier(), vec![b]);
+        assert!(!working.is_frontier(a));
+        assert_eq!(working.unloaded_neighbors(b), HashSet::from([c, p]));
+
+        let added = working.expand_frontier(&[a, b], &long_term).unwrap();
+        assert_eq!(
+            added.into_iter().collect::<HashSet<_>>(),
+            HashSet::from([c, p])
+        );
+        assert!(working.has_edge(b_c) && working.has_edge(b_p));
+        //边界随加载向外推移
+        assert_eq!(working.frontier(), vec![c]);
+        assert!(
+            working
+                .expand_frontier(&[b], &long_term)
+                .unwrap()
+                .is_empty()
+        );
+
+        assert_eq!(working.expand_frontier(&[c], &long_term).unwrap(), vec![d]);
+        assert!(working.has_edge(c_d));
+        assert!(working.frontier().is_empty());
+
+        //删除节点后，它的邻居重新成为边界
+        working.remove_single_node(c);
+        let mut expected = vec![b, d];
+        expected.sort();
+        assert_eq!(working.frontier(), expected);
+        assert_eq!(working.unloaded_neighbors(d), HashSet::from([c]));
+
+        //子图外指入的边被标记为未加载的邻居
+        let single = long_term.sub_cluster([c], []).materialize();
+        assert_eq!(
+            single.cluster().unloaded_neighbors(c),
+            HashSet::from([b, d])
+        );
+        let mut single = single.into_parts().0;
+        assert!(matches!(
+            single.expand_frontier(&[c], &FailingLoader),
+            Err(ClusterError::Load(_))
+        ));
+        assert!(!single.mark_unloaded_neighbors(MemoryId::new(), [c]));
+    }
+
+    #[test]
+    fn test_link_schema_validation() {
+        let note = |mem_type| MemoryNoteBuilder::new(mem_type).build().unwrap();
+        let concept = note(MemoryType::Semantic(SemMemory::new(
+            "咖啡".to_string(),
+            ConceptType::Entity,
+            "一种饮品".to_string(),
+        )));
+        let cafe = note(MemoryType::Situation(
+            AbstractSituation::Location(Location {
+                name: "咖啡馆".to_string(),
+                coordinates: "街角".to_string(),
+            })
+            .into(),
+        ));
+        let date = note(MemoryType::Situation(
+            SpecificSituation::new(
+                "和小林在咖啡馆见面".to_string(),
+                Utc::now(),
+                Context::new(
+                    None,
+                    vec![],
+                    vec![],
+                    vec![],
+                    Environment {
+                        atmosphere: "安静".to_string(),
+                        tone: "温和".to_string(),
+                    },
+                    vec![],
+                ),
+            )
+            .into(),
+        ));
+        let order = note(MemoryType::Procedure(ProcMemory::new(Action::new(
+            "点一杯拿铁".to_string(),
+            ActionType::new_speak(),
+        ))));
+        let trig = |prob| MemoryLinkType::Proc(ProcMemLink::TrigToAction(TrigToAction::new(prob)));
+        let related = MemoryLinkType::Sem(SemMemLink::new("相关".to_string(), 1.0, 1.0));
+
+        assert!(related.validate(&concept, &concept).is_ok());
+        assert_eq!(
+            related.validate(&concept, &cafe),
+            Err(LinkSchemaError::InvalidEndpoints {
+                kind: LinkKind::Semantic,
+                from: MemoryKind::Semantic,
+                to: MemoryKind::Situation,
+            })
+        );
+        assert!(trig(0.5).validate(&cafe, &order).is_ok());
+        assert!(trig(0.5).validate(&concept, &order).is_ok());
+        assert!(trig(0.5).validate(&cafe, &concept).is_err());
+        assert!(trig(0.5).validate(&order, &order).is_err());
+        assert_eq!(
+            trig(1.5).validate(&cafe, &order),
+            Err(LinkSchemaError::InvalidProbability(1.5))
+        );
+
+        let index = |from: &MemoryNote, to: &MemoryNote| {
+            MemoryLinkType::Situation(SituationMemLink::AbstractToSpecific(
+                AbstractToSpecific::new(from.id(), to.id()),
+            ))
+        };
+        assert!(index(&cafe, &date).validate(&cafe, &date).is_ok());
+        assert!(index(&date, &cafe).validate(&date, &cafe).is_err());
+        assert_eq!(
+            index(&cafe, &date).validate(&cafe, &cafe),
+            Err(LinkSchemaError::InvalidEndpoints {
+                kind: LinkKind::Situation,
+                from: MemoryKind::Situation,
+                to: MemoryKind::Situation,
+            })
+        );
+        assert_eq!(
+            index(&cafe, &concept).validate(&cafe, &date),
+            Err(LinkSchemaError::EndpointMismatch)
+        );
+
+        let about = MemoryLinkType::Cross(CrossMemLink::SemSituation(SemSituation::new(0.6)));
+        assert!(about.validate(&concept, &date).is_ok());
+        assert!(about.validate(&cafe, &concept).is_ok());
+        assert!(about.validate(&concept, &concept).is_err());
+        assert!(about.validate(&concept, &order).is_err());
+        assert_eq!(about.kind(), LinkKind::Cross);
+        assert!((about.strength() - 0.6).abs() < 1e-6);
+
+        //merge_edge时丢弃不合法的边并告知调用方，目标稍后才加入的待连接边同样会被检查
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let [a, b, c] = [(); 3].map(|_| MemoryId::new());
+        let valid = MemoryLink::new(a, b, related.clone());
+        let invalid = MemoryLink::new(a, b, trig(0.5));
+        let late = MemoryLink::new(a, c, trig(0.5));
+        let mut cluster = MemoryCluster::new();
+        let rejected = cluster.merge(vec![
+            linked_note(
+                &model,
+                a,
+                "咖啡",
+                vec![valid.clone(), invalid.clone(), late.clone()],
+                Utc::now(),
+            ),
+            linked_note(&model, b, "拿铁", vec![], Utc::now()),
+        ]);
+        assert_eq!(
+            rejected,
+            vec![RejectedLink::new(
+                invalid.id(),
+                LinkSchemaError::InvalidEndpoints {
+                    kind: LinkKind::Procedure,
+                    from: MemoryKind::Semantic,
+                    to: MemoryKind::Semantic,
+                },
+            )]
+        );
+        assert!(cluster.has_edge(valid.id()));
+        assert!(!cluster.has_edge(invalid.id()));
+        assert_eq!(cluster.unloaded_neighbors(a), HashSet::from([c]));
+
+        //节点本身仍然加入，被丢弃的是以它为目标的待连接边
+        let rejected = cluster
+            .add_single_node(linked_note(&model, c, "美式", vec![], Utc::now()))
+            .unwrap_err();
+        assert_eq!(
+            rejected.iter().map(|r| r.link()).collect::<Vec<_>>(),
+            vec![late.id()]
+        );
+        assert!(cluster.contains_node(c));
+        assert!(!cluster.has_edge(late.id()));
+        assert!(cluster.unloaded_neighbors(a).is_empty());
+        assert_eq!(cluster.graph().edge_count(), 1);
+
+        //并入其他记忆簇时，被丢弃的边记录在MergeReport中
+        let d = MemoryId::new();
+        let bad = MemoryLink::new(d, a, trig(0.5));
+        let mut incoming = MemoryCluster::new();
+        incoming.merge(vec![linked_note(
+            &model,
+            d,
+            "摩卡",
+            vec![bad.clone()],
+            Utc::now(),
+        )]);
+        let report = cluster.merge_cluster(incoming, MergePolicy::KeepExisting);
+        assert_eq!(report.added(), &[d]);
+        assert_eq!(report.rejected().len(), 1);
+        assert_eq!(report.rejected()[0].link(), bad.id());
+        assert!(!cluster.has_edge(bad.id()));
+    }
+
+    #[test]
+    fn test_filter_notes_linked_to() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let (a, b, c) = (MemoryId::new(), MemoryId::new(), MemoryId::new());
+        let a_to_b = MemoryLink::new(
+            a,
+            b,
+            MemoryLinkType::Sem(SemMemLink::new("相关".to_string(), 1.0, 1.0)),
+        );
+        let mut cluster = MemoryCluster::new();
+        cluster.merge(vec![
+            linked_note(&model, a, "咖啡", vec![a_to_b], Utc::now()),
+            linked_note(&model, b, "咖啡豆", vec![], Utc::now()),
+            linked_note(&model, c, "钢琴", vec![], Utc::now()),
+        ]);
+        let linked = |filter: MemoryFilter| {
+            cluster
+                .filter_notes(&filter)
+                .map(|note| note.id())
+                .collect::<HashSet<_>>()
+        };
+
+        //两个方向的边都算作相连
+        let to_b = MemoryFilter::LinkedTo {
+            id: b,
+            link_kind: None,
+        };
+        assert_eq!(linked(to_b.clone()), HashSet::from([a]));
+        assert_eq!(
+            linked(MemoryFilter::LinkedTo {
+                id: a,
+                link_kind: None,
+            }),
+            HashSet::from([b])
+        );
+        assert_eq!(
+            linked(MemoryFilter::LinkedTo {
+                id: b,
+                link_kind: Some(LinkKind::Semantic),
+            }),
+            HashSet::from([a])
+        );
+        assert!(
+            linked(MemoryFilter::LinkedTo {
+                id: b,
+                link_kind: Some(LinkKind::Cross),
+            })
+            .is_empty()
+        );
+        assert!(
+            linked(MemoryFilter::LinkedTo {
+                id: MemoryId::new(),
+                link_kind: None,
+            })
+            .is_empty()
+        );
+        assert_eq!(linked(to_b.negate()), HashSet::from([b, c]));
+    }
+}
@@ -1,12 +1,19 @@
 use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use uuid::Uuid;
 
 use crate::memory::{
-    memory_links::proc_mem::ProcMemLink, memory_links::sem_mem::SemMemLink, memory_note::MemoryId,
+    memory_links::cross_mem::CrossMemLink,
+    memory_links::proc_mem::ProcMemLink,
+    memory_links::sem_mem::SemMemLink,
+    memory_links::situation_mem::SituationMemLink,
+    memory_note::situation_mem::SituationType,
+    memory_note::{MemoryId, MemoryKind, MemoryNote, MemoryType},
 };
 
+pub mod cross_mem;
 pub mod proc_mem;
 pub mod sem_mem;
 
@@ -41,6 +48,124 @@ pub struct MemoryLink {
 pub enum MemoryLinkType {
     Proc(ProcMemLink),
     Sem(SemMemLink),
+    Situation(SituationMemLink),
+    Cross(CrossMemLink),
+}
+impl MemoryLinkType {
+    pub fn kind(&self) -> LinkKind {
+        match self {
+            MemoryLinkType::Proc(_) => LinkKind::Procedure,
+            MemoryLinkType::Sem(_) => LinkKind::Semantic,
+            MemoryLinkType::Situation(_) => LinkKind::Situation,
+            MemoryLinkType::Cross(_) => LinkKind::Cross,
+        }
+    }
+    /// 连接强度，作为图算法（如PPR）中的边权，总是非负
+    pub fn strength(&self) -> f32 {
+        let strength = match self {
+            MemoryLinkType::Sem(sem) => sem.intensity * sem.confidence,
+            MemoryLinkType::Proc(ProcMemLink::TrigToAction(trig)) => trig.get_prob(),
+            //抽象情境是具体情境的索引，总是完全连通
+            MemoryLinkType::Situation(SituationMemLink::AbstractToSpecific(_)) => 1.0,
+            MemoryLinkType::Cross(CrossMemLink::SemSituation(link)) => link.get_intensity(),
+        };
+        strength.max(0.0)
+    }
+    /// 检查连接能否从from指向to：
+    /// - 语义连接只在语义记忆之间
+    /// - TrigToAction只能由情境记忆（触发）或语义记忆（自我认知）指向程序性记忆，概率在[0, 1]内
+    /// - AbstractToSpecific只能由抽象情境指向具体情境，且与连接中记录的记忆一致
+    /// - 跨子图的SemSituation连接一端为语义记忆，另一端为情境记忆
+    pub fn validate(&self, from: &MemoryNote, to: &MemoryNote) -> Result<(), LinkSchemaError> {
+        let (from_kind, to_kind) = (
+            MemoryKind::of(from.mem_type()),
+            MemoryKind::of(to.mem_type()),
+        );
+        let endpoints_valid = match self {
+            MemoryLinkType::Sem(_) => {
+                from_kind == MemoryKind::Semantic && to_kind == MemoryKind::Semantic
+            }
+            MemoryLinkType::Proc(ProcMemLink::TrigToAction(trig)) => {
+                if !(0.0..=1.0).contains(&trig.get_prob()) {
+                    return Err(LinkSchemaError::InvalidProbability(trig.get_prob()));
+                }
+                from_kind != MemoryKind::Procedure && to_kind == MemoryKind::Procedure
+            }
+            MemoryLinkType::Situation(SituationMemLink::AbstractToSpecific(link)) => {
+                let valid = matches!(
+                    (from.mem_type(), to.mem_type()),
+                    (
+                        MemoryType::Situation(SituationType::AbstractSituation(_)),
+                        MemoryType::Situation(SituationType::SpecificSituation(_)),
+                    )
+                );
+                if valid
+                    && (link.get_abstract_memory_id() != from.id()
+                        || *link.get_specific_memories() != to.id())
+                {
+                    return Err(LinkSchemaError::EndpointMismatch);
+                }
+                valid
+            }
+            MemoryLinkType::Cross(CrossMemLink::SemSituation(_)) => matches!(
+                (from_kind, to_kind),
+                (MemoryKind::Semantic, MemoryKind::Situation)
+                    | (MemoryKind::Situation, MemoryKind::Semantic)
+            ),
+        };
+        if endpoints_valid {
+            Ok(())
+        } else {
+            Err(LinkSchemaError::InvalidEndpoints {
+                kind: self.kind(),
+                from: from_kind,
+                to: to_kind,
+            })
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum LinkSchemaError {
+    #[error("{kind:?} link cannot go from {from:?} memory to {to:?} memory")]
+    InvalidEndpoints {
+        kind: LinkKind,
+        from: MemoryKind,
+        to: MemoryKind,
+    },
+    #[error("link endpoints do not match the memories recorded in the link")]
+    EndpointMismatch,
+    #[error("transition probability {0} is out of [0, 1]")]
+    InvalidProbability(f32),
+}
+
+//加入记忆簇时因不符合连接模式而被丢弃的边
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("link {link} rejected: {error}")]
+pub struct RejectedLink {
+    link: LinkId,
+    #[source]
+    error: LinkSchemaError,
+}
+impl RejectedLink {
+    pub fn new(link: LinkId, error: LinkSchemaError) -> Self {
+        Self { link, error }
+    }
+    pub fn link(&self) -> LinkId {
+        self.link
+    }
+    pub fn error(&self) -> &LinkSchemaError {
+        &self.error
+    }
+}
+
+//连接的种类，对应MemoryLinkType的各个变体
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LinkKind {
+    Procedure,
+    Semantic,
+    Situation,
+    Cross,
 }
 
 impl MemoryLink {
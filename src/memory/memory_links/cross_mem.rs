@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+///跨子图的Link，子图内的连接见各子图的Link
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CrossMemLink {
+    SemSituation(SemSituation), //语义记忆 <--> 情境记忆，方向由边的方向决定
+}
+
+//概念与一类（抽象）或一个（具体）情境之间的联系，例如“项目” --> “赶项目”
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SemSituation {
+    pub intensity: f32, //连接强度
+}
+impl SemSituation {
+    pub fn new(intensity: f32) -> Self {
+        SemSituation { intensity }
+    }
+
+    pub fn get_intensity(&self) -> f32 {
+        self.intensity
+    }
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity;
+    }
+}
@@ -92,6 +92,23 @@ pub enum MemoryType {
     Procedure(ProcMemory),
 }
 
+//记忆的类型，对应MemoryType的各个变体
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MemoryKind {
+    Semantic,
+    Situation,
+    Procedure,
+}
+impl MemoryKind {
+    pub fn of(mem_type: &MemoryType) -> Self {
+        match mem_type {
+            MemoryType::Semantic(_) => Self::Semantic,
+            MemoryType::Situation(_) => Self::Situation,
+            MemoryType::Procedure(_) => Self::Procedure,
+        }
+    }
+}
+
 //Builder pattern
 pub struct MemoryNoteBuilder {
     id: Option<MemoryId>,
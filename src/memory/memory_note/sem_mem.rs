@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 // 概念类型
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ConceptType {
     Entity,
     Abstract,
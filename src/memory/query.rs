@@ -1,4 +1,7 @@
 use crate::memory::memory_note::MemoryId;
 
 pub mod compute;
+pub mod explain;
+pub mod filter;
 pub mod retrieve;
+pub mod scoring;
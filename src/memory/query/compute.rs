@@ -1,19 +1,28 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::memory::query::explain::ScoreExplanation;
+use crate::memory::query::scoring::{IntensitySensitivity, ScoringProfile, UnitAggregation};
 use crate::memory::{
     embedding::{
-        note::{EmbeddedMemoryNote, MemoryEmbedding, MemoryEmbeddingVariant},
+        note::{
+            EmbeddedMemoryNote, EmbeddedMemoryNoteRef, MemoryEmbedding, MemoryEmbeddingVariant,
+        },
         query::{
             note::{MemoryRetrieveQueryEmbedding, MemoryRetrieveQueryVariantEmbedding},
             sem::SemanticQueryUnitEmbedding,
             situation::{
-                environment::EnvironmentQueryUnitEmbedding, event::EventQueryUnitEmbedding,
-                location::LocationQueryUnitEmbedding, participant::ParticipantQueryUnitEmbedding,
+                emotion::EmotionQueryUnitEmbedding, environment::EnvironmentQueryUnitEmbedding,
+                event::EventQueryUnitEmbedding, location::LocationQueryUnitEmbedding,
+                participant::ParticipantQueryUnitEmbedding, sensory::SensoryQueryUnitEmbedding,
                 SituationQueryUnitEmbedding,
             },
         },
         sem::SemanticEmbedding,
         situation::{
-            environment::EnvironmentEmbedding, event::EventEmbedding, location::LocationEmbedding,
-            participant::ParticipantEmbedding, AbstractSituationEmbedding, SituationEmbedding,
+            emotion::EmotionEmbedding, environment::EnvironmentEmbedding, event::EventEmbedding,
+            location::LocationEmbedding, participant::ParticipantEmbedding,
+            sensory_data::SensoryDataEmbedding, AbstractSituationEmbedding, SituationEmbedding,
             SpecificSituationEmbedding,
         },
         Embeddable, EmbeddingCalcResult, EmbeddingModel,
@@ -28,15 +37,63 @@ use crate::memory::{
             SemanticQueryUnit,
         },
     },
+    record::Record,
 };
 
+//一次计分的上下文：计分配置与当前时间，同一轮排序中的所有记忆使用同一个now，保证分数可比且可复现
+#[derive(Debug, Clone, Copy)]
+pub struct ComputeContext<'a> {
+    profile: &'a ScoringProfile,
+    now: DateTime<Utc>,
+}
+impl<'a> ComputeContext<'a> {
+    pub fn new(profile: &'a ScoringProfile) -> Self {
+        Self {
+            profile,
+            now: Utc::now(),
+        }
+    }
+    pub fn with_now(mut self, now: DateTime<Utc>) -> Self {
+        self.now = now;
+        self
+    }
+    pub fn profile(&self) -> &'a ScoringProfile {
+        self.profile
+    }
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+}
+
+//每个实现只需给出explain，分数即解释树根节点的分数，保证两者一致
 pub trait AnonymousQueryCompute {
     type Query;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32>;
+    /// 计算分数，并给出与查询结构对应的各分量贡献
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation>;
+    fn anonymous_compute(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<f32> {
+        Ok(self.explain(query, ctx)?.score())
+    }
 }
 
 pub trait QueryCompute: AnonymousQueryCompute {
-    fn compute(&self, query: &Self::Query) -> EmbeddingCalcResult<QueryComputeResult>;
+    fn compute(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<QueryComputeResult>;
+    fn compute_explained(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<(QueryComputeResult, ScoreExplanation)>;
 }
 
 pub struct QueryComputeResult {
@@ -52,24 +109,41 @@ impl QueryComputeResult {
 ////////////////////////////////////////////////////////////
 impl AnonymousQueryCompute for LocationEmbedding {
     type Query = LocationQueryUnitEmbedding;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
         let name_score = self.name().cosine_similarity(query.name())?;
         let coordinates_score = query
             .coordinates()
             .map(|coordinate| coordinate.cosine_similarity(self.coordinates()))
             .transpose()?;
 
-        if let Some(coord_score) = coordinates_score {
-            Ok(name_score * 0.6 + coord_score * 0.4)
-        } else {
-            Ok(name_score)
-        }
+        let weights = &ctx.profile().location;
+        Ok(ScoreExplanation::weighted(
+            "location",
+            [
+                (
+                    weights.name,
+                    Some(ScoreExplanation::leaf("name", name_score)),
+                ),
+                (
+                    weights.coordinates,
+                    coordinates_score.map(|score| ScoreExplanation::leaf("coordinates", score)),
+                ),
+            ],
+        ))
     }
 }
 
 impl AnonymousQueryCompute for ParticipantEmbedding {
     type Query = ParticipantQueryUnitEmbedding;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
         let name_score = query
             .name()
             .map(|name| name.cosine_similarity(self.name()))
@@ -80,18 +154,30 @@ impl AnonymousQueryCompute for ParticipantEmbedding {
             .map(|role| role.cosine_similarity(self.role()))
             .transpose()?;
 
-        match (name_score, role_score) {
-            (Some(name_score), Some(role_score)) => Ok(name_score * 0.6 + role_score * 0.4),
-            (Some(name_score), None) => Ok(name_score),
-            (None, Some(role_score)) => Ok(role_score),
-            (None, None) => Ok(0.0),
-        }
+        let weights = &ctx.profile().participant;
+        Ok(ScoreExplanation::weighted(
+            "participants",
+            [
+                (
+                    weights.name,
+                    name_score.map(|score| ScoreExplanation::leaf("name", score)),
+                ),
+                (
+                    weights.role,
+                    role_score.map(|score| ScoreExplanation::leaf("role", score)),
+                ),
+            ],
+        ))
     }
 }
 
 impl AnonymousQueryCompute for EnvironmentEmbedding {
     type Query = EnvironmentQueryUnitEmbedding;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
         let atmosphere_score = query
             .atmosphere()
             .map(|atmosphere| atmosphere.cosine_similarity(self.atmosphere()))
@@ -102,20 +188,30 @@ impl AnonymousQueryCompute for EnvironmentEmbedding {
             .map(|tone| tone.cosine_similarity(self.tone()))
             .transpose()?;
 
-        match (atmosphere_score, tone_score) {
-            (Some(atmosphere_score), Some(tone_score)) => {
-                Ok(atmosphere_score * 0.5 + tone_score * 0.5)
-            }
-            (Some(atmosphere_score), None) => Ok(atmosphere_score),
-            (None, Some(tone_score)) => Ok(tone_score),
-            (None, None) => Ok(0.0),
-        }
+        let weights = &ctx.profile().environment;
+        Ok(ScoreExplanation::weighted(
+            "environment",
+            [
+                (
+                    weights.atmosphere,
+                    atmosphere_score.map(|score| ScoreExplanation::leaf("atmosphere", score)),
+                ),
+                (
+                    weights.tone,
+                    tone_score.map(|score| ScoreExplanation::leaf("tone", score)),
+                ),
+            ],
+        ))
     }
 }
 
 impl AnonymousQueryCompute for EventEmbedding {
     type Query = EventQueryUnitEmbedding;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
         let action_score = self.action().cosine_similarity(query.action())?;
 
         let initiator_score = query
@@ -128,113 +224,249 @@ impl AnonymousQueryCompute for EventEmbedding {
             .map(|target| target.cosine_similarity(self.target()))
             .transpose()?;
 
-        match (initiator_score, target_score) {
-            (Some(initiator_score), Some(target_score)) => {
-                Ok(initiator_score * 0.3 + target_score * 0.3 + action_score * 0.4)
-            }
-            (Some(initiator_score), None) => Ok(initiator_score * 0.4 + action_score * 0.6),
-            (None, Some(target_score)) => Ok(target_score * 0.4 + action_score * 0.6),
-            (None, None) => Ok(action_score),
+        let weights = &ctx.profile().event;
+        Ok(ScoreExplanation::weighted(
+            "event",
+            [
+                (
+                    weights.action,
+                    Some(ScoreExplanation::leaf("action", action_score)),
+                ),
+                (
+                    weights.initiator,
+                    initiator_score.map(|score| ScoreExplanation::leaf("initiator", score)),
+                ),
+                (
+                    weights.target,
+                    target_score.map(|score| ScoreExplanation::leaf("target", score)),
+                ),
+            ],
+        ))
+    }
+}
+
+//相似度与强度因子相乘，强度因子作为乘性子节点记录
+fn intensity_explanation(
+    facet: &'static str,
+    similarity: f32,
+    sensitivity: f32,
+    query_intensity: Option<f32>,
+    memory_intensity: f32,
+) -> ScoreExplanation {
+    let factor = IntensitySensitivity::factor(sensitivity, query_intensity, memory_intensity);
+    let note = match query_intensity {
+        Some(query_intensity) => {
+            format!("multiplier, query {query_intensity:.2} vs memory {memory_intensity:.2}")
         }
+        None => format!("multiplier, memory {memory_intensity:.2}"),
+    };
+    ScoreExplanation::leaf(facet, similarity * factor)
+        .with_child(ScoreExplanation::leaf("similarity", similarity))
+        .with_child(ScoreExplanation::leaf("intensity", factor).with_note(note))
+}
+
+impl AnonymousQueryCompute for EmotionEmbedding {
+    type Query = EmotionQueryUnitEmbedding;
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
+        let similarity = query.emotion().cosine_similarity(self.emotion())?;
+        Ok(intensity_explanation(
+            "emotion",
+            similarity,
+            ctx.profile().intensity.emotion,
+            query.intensity(),
+            self.intensity(),
+        ))
+    }
+}
+
+impl AnonymousQueryCompute for SensoryDataEmbedding {
+    type Query = SensoryQueryUnitEmbedding;
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
+        let similarity = query.sensory().cosine_similarity(self.sensory())?;
+        Ok(intensity_explanation(
+            "sensory",
+            similarity,
+            ctx.profile().intensity.sensory,
+            query.intensity(),
+            self.intensity(),
+        ))
     }
 }
 
 impl AnonymousQueryCompute for SpecificSituationEmbedding {
     type Query = SituationQueryUnitEmbedding;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
-        let narrative_score = query
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
+        let narrative = query
             .narrative()
             .map(|narrative| narrative.cosine_similarity(self.narrative()))
-            .transpose()?;
+            .transpose()?
+            .map(|score| ScoreExplanation::leaf("narrative", score));
 
         //location
-        let location_score = if let Some(query_location) = query.location() {
+        let location = if let Some(query_location) = query.location() {
             self.context()
                 .location()
-                .map(|location| location.anonymous_compute(query_location))
+                .map(|location| location.explain(query_location, ctx))
                 .transpose()?
         } else {
             None
         };
 
         //participants
-        let participants_score = if let Some(query_participants) = query.participants() {
+        let participants = if let Some(query_participants) = query.participants() {
             self.context()
                 .fused_participant()
-                .map(|participants| participants.anonymous_compute(query_participants))
+                .map(|participants| participants.explain(query_participants, ctx))
                 .transpose()?
         } else {
             None
         };
 
         //environment
-        let environment_score = query
+        let environment = query
             .environment()
-            .map(|env| self.context().environment().anonymous_compute(env))
+            .map(|env| self.context().environment().explain(env, ctx))
             .transpose()?;
 
         //event
-        let event_score = if let Some(query_event) = query.event() {
+        let event = if let Some(query_event) = query.event() {
             self.context()
                 .fused_event()
-                .map(|event| event.anonymous_compute(query_event))
+                .map(|event| event.explain(query_event, ctx))
                 .transpose()?
         } else {
             None
         };
 
-        //fuse score
-        let score_vec = narrative_score
-            .into_iter()
-            .chain(location_score.into_iter())
-            .chain(participants_score.into_iter())
-            .chain(environment_score.into_iter())
-            .chain(event_score.into_iter())
-            .collect::<Vec<_>>();
+        //emotion
+        let emotion = if let Some(query_emotion) = query.emotion() {
+            self.context()
+                .fused_emotion()
+                .map(|emotion| emotion.explain(query_emotion, ctx))
+                .transpose()?
+        } else {
+            None
+        };
 
-        let len = score_vec.len();
-        Ok(score_vec.into_iter().map(|i| i / len as f32).sum::<f32>())
+        //sensory
+        let sensory = if let Some(query_sensory) = query.sensory() {
+            self.context()
+                .fused_sensory_data()
+                .map(|sensory| sensory.explain(query_sensory, ctx))
+                .transpose()?
+        } else {
+            None
+        };
+
+        //time span：硬约束不满足时直接为0，软约束按距离衰减后与内容分数相乘
+        if !query.admits(Some(self.time_span())) {
+            return Ok(ScoreExplanation::leaf("specific_situation", 0.0)
+                .with_note("filtered by hard time span"));
+        }
+        let time_score = query.time_score(self.time_span());
+
+        //fuse score
+        let weights = &ctx.profile().situation;
+        let facets = [
+            (weights.narrative, narrative),
+            (weights.location, location),
+            (weights.participants, participants),
+            (weights.environment, environment),
+            (weights.event, event),
+            (weights.emotion, emotion),
+            (weights.sensory, sensory),
+        ];
+        if facets.iter().all(|(_, facet)| facet.is_none()) {
+            //仅有时间约束的查询，时间分数即为总分
+            let score = if query.has_time_constraint() {
+                time_score
+            } else {
+                0.0
+            };
+            return Ok(ScoreExplanation::leaf("specific_situation", score));
+        }
+        let explanation = ScoreExplanation::weighted("specific_situation", facets);
+        if !query.has_time_constraint() {
+            return Ok(explanation);
+        }
+        let score = explanation.score() * time_score;
+        Ok(explanation
+            .with_score(score)
+            .with_child(ScoreExplanation::leaf("time_span", time_score).with_note("multiplier")))
     }
 }
 
 impl AnonymousQueryCompute for AbstractSituationEmbedding {
     type Query = SituationQueryUnitEmbedding;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
-        match self {
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
+        //抽象情景不具有时间，无法满足硬性时间约束，软约束则不影响其分数
+        if !query.admits(None) {
+            return Ok(ScoreExplanation::leaf("abstract_situation", 0.0)
+                .with_note("filtered by hard time span"));
+        }
+        let facet = match self {
             AbstractSituationEmbedding::Location(loc) => query
                 .location()
-                .map(|q_loc| loc.anonymous_compute(q_loc))
-                .unwrap_or(Ok(0.0)),
+                .map(|q_loc| loc.explain(q_loc, ctx))
+                .transpose()?,
             AbstractSituationEmbedding::Environment(env) => query
                 .environment()
-                .map(|q_env| env.anonymous_compute(q_env))
-                .unwrap_or(Ok(0.0)),
+                .map(|q_env| env.explain(q_env, ctx))
+                .transpose()?,
             AbstractSituationEmbedding::Event(event) => query
                 .event()
-                .map(|q_event| event.anonymous_compute(q_event))
-                .unwrap_or(Ok(0.0)),
+                .map(|q_event| event.explain(q_event, ctx))
+                .transpose()?,
             AbstractSituationEmbedding::Participant(participant) => query
                 .participants()
-                .map(|q_participant| participant.anonymous_compute(q_participant))
-                .unwrap_or(Ok(0.0)),
-        }
+                .map(|q_participant| participant.explain(q_participant, ctx))
+                .transpose()?,
+        };
+        Ok(match facet {
+            Some(facet) => ScoreExplanation::weighted("abstract_situation", [(1.0, Some(facet))]),
+            None => ScoreExplanation::leaf("abstract_situation", 0.0)
+                .with_note("query has no matching facet"),
+        })
     }
 }
 
 impl AnonymousQueryCompute for SituationEmbedding {
     type Query = SituationQueryUnitEmbedding;
-    //TODO: add time span score count
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
         match self {
-            Self::Specific(specific) => specific.anonymous_compute(query),
-            Self::Abstract(abstract_sit) => abstract_sit.anonymous_compute(query),
+            Self::Specific(specific) => specific.explain(query, ctx),
+            Self::Abstract(abstract_sit) => abstract_sit.explain(query, ctx),
         }
     }
 }
 
 impl AnonymousQueryCompute for SemanticEmbedding {
     type Query = SemanticQueryUnitEmbedding;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
         let concept_main_score = query
             .concept_identifier()
             .map(|con| con.cosine_similarity(self.content()))
@@ -249,69 +481,371 @@ impl AnonymousQueryCompute for SemanticEmbedding {
             .map(|description| description.cosine_similarity(self.description()))
             .transpose()?;
 
-        let concept_score = match (concept_main_score, concept_aliases_score) {
-            (Some(main_score), Some(aliases_score)) => 0.7 * main_score + 0.3 * aliases_score,
-            (None, None) => 0.0,
+        let weights = &ctx.profile().semantic;
+        let concept = match (concept_main_score, concept_aliases_score) {
+            (Some(main_score), Some(aliases_score)) => Some(ScoreExplanation::weighted(
+                "concept",
+                [
+                    (
+                        weights.concept_main,
+                        Some(ScoreExplanation::leaf("concept_main", main_score)),
+                    ),
+                    (
+                        weights.concept_alias,
+                        Some(ScoreExplanation::leaf("concept_alias", aliases_score)),
+                    ),
+                ],
+            )),
+            (None, None) => None,
             _ => unreachable!(
                 "main_score and aliases_score all compute from query.concept_identifier(), so they must be Some or None simultaneously"
             ),
         };
 
-        if let Some(description_score) = description_score {
-            Ok(concept_score * 0.5 + description_score * 0.5)
-        } else {
-            Ok(concept_score)
-        }
+        Ok(ScoreExplanation::weighted(
+            "semantic",
+            [
+                (weights.concept, concept),
+                (
+                    weights.description,
+                    description_score.map(|score| ScoreExplanation::leaf("description", score)),
+                ),
+            ],
+        ))
+    }
+}
+
+//跨类型比较：语义记忆与情境查询单元，以叙述与概念内容、描述的相似度衡量
+fn semantic_against_situation(
+    sem: &SemanticEmbedding,
+    query: &SituationQueryUnitEmbedding,
+) -> EmbeddingCalcResult<Option<ScoreExplanation>> {
+    query
+        .narrative()
+        .map(|narrative| {
+            let content_score = narrative.cosine_similarity(sem.content())?;
+            let description_score = narrative.cosine_similarity(sem.description())?;
+            Ok(
+                ScoreExplanation::leaf("narrative", content_score.max(description_score))
+                    .with_note("narrative vs semantic content/description"),
+            )
+        })
+        .transpose()
+}
+
+//跨类型比较：具体情境记忆与语义查询单元，以概念、描述与情境叙述的相似度衡量
+fn situation_against_semantic(
+    sit: &SituationEmbedding,
+    query: &SemanticQueryUnitEmbedding,
+    profile: &ScoringProfile,
+) -> EmbeddingCalcResult<Option<ScoreExplanation>> {
+    let Some(specific) = sit.to_specific() else {
+        return Ok(None);
+    };
+    let concept_score = query
+        .concept_identifier()
+        .map(|con| con.cosine_similarity(specific.narrative()))
+        .transpose()?;
+    let description_score = query
+        .description()
+        .map(|description| description.cosine_similarity(specific.narrative()))
+        .transpose()?;
+    if concept_score.is_none() && description_score.is_none() {
+        return Ok(None);
     }
+    Ok(Some(ScoreExplanation::weighted(
+        "semantic",
+        [
+            (
+                profile.semantic.concept,
+                concept_score.map(|score| ScoreExplanation::leaf("concept", score)),
+            ),
+            (
+                profile.semantic.description,
+                description_score.map(|score| ScoreExplanation::leaf("description", score)),
+            ),
+        ],
+    )))
 }
 
+//各查询单元的分数按配置合并后，再按记忆类型校准到[0, 1]，使不同形状的查询、不同类型的记忆分数可比；
+//记忆与查询类型不同时做跨类型比较并打折，无法比较时为0
 impl AnonymousQueryCompute for MemoryEmbeddingVariant {
     type Query = MemoryRetrieveQueryVariantEmbedding;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
-        match (self, query) {
-            (Self::Semantic(sem), MemoryRetrieveQueryVariantEmbedding::Semantic(q_sem)) => {
-                let score_vec = q_sem
-                    .into_iter()
-                    .map(|q_sem_unit| sem.anonymous_compute(q_sem_unit))
-                    .collect::<Result<Vec<_>, _>>()?;
-                Ok(score_vec.into_iter().sum::<f32>())
-            }
-            (Self::Situation(sit), MemoryRetrieveQueryVariantEmbedding::Situation(q_sit)) => {
-                let score_vec = q_sit
-                    .into_iter()
-                    .map(|q_sit_unit| sit.anonymous_compute(q_sit_unit))
-                    .collect::<Result<Vec<_>, _>>()?;
-                Ok(score_vec.into_iter().sum::<f32>())
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
+        let normalization = &ctx.profile().normalization;
+        let cross_type = |unit: Option<ScoreExplanation>| {
+            unit.map(|unit| {
+                let score = unit.score() * normalization.cross_type;
+                unit.with_score(score).with_note("cross type")
+            })
+        };
+        let (units, calibration) = match (self, query) {
+            (Self::Semantic(sem), MemoryRetrieveQueryVariantEmbedding::Semantic(q_sem)) => (
+                q_sem
+                    .iter()
+                    .map(|q_sem_unit| sem.explain(q_sem_unit, ctx).map(Some))
+                    .collect::<Result<Vec<_>, _>>()?,
+                normalization.semantic,
+            ),
+            (Self::Situation(sit), MemoryRetrieveQueryVariantEmbedding::Situation(q_sit)) => (
+                q_sit
+                    .iter()
+                    .map(|q_sit_unit| sit.explain(q_sit_unit, ctx).map(Some))
+                    .collect::<Result<Vec<_>, _>>()?,
+                normalization.situation,
+            ),
+            (Self::Semantic(sem), MemoryRetrieveQueryVariantEmbedding::Situation(q_sit)) => (
+                q_sit
+                    .iter()
+                    .map(|q_sit_unit| Ok(cross_type(semantic_against_situation(sem, q_sit_unit)?)))
+                    .collect::<EmbeddingCalcResult<Vec<_>>>()?,
+                normalization.semantic,
+            ),
+            (Self::Situation(sit), MemoryRetrieveQueryVariantEmbedding::Semantic(q_sem)) => (
+                q_sem
+                    .iter()
+                    .map(|q_sem_unit| {
+                        Ok(cross_type(situation_against_semantic(
+                            sit,
+                            q_sem_unit,
+                            ctx.profile(),
+                        )?))
+                    })
+                    .collect::<EmbeddingCalcResult<Vec<_>>>()?,
+                normalization.situation,
+            ),
+            (Self::Procedure(), _) => {
+                return Ok(ScoreExplanation::leaf("variant", 0.0)
+                    .with_note("procedure memory is not scored"));
             }
-            (_, _) => Ok(0.0),
+        };
+
+        let units = units.into_iter().flatten().collect::<Vec<_>>();
+        let Some(raw) = normalization
+            .unit_aggregation
+            .aggregate(units.iter().map(|unit| unit.score()))
+        else {
+            return Ok(ScoreExplanation::leaf("variant", 0.0).with_note("no comparable unit"));
+        };
+        //均值时每个单元权重相同；取最大值时只有最好的单元有权重
+        let count = units.len();
+        let best = units
+            .iter()
+            .map(|unit| unit.score())
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index);
+        let children = units.into_iter().enumerate().map(|(index, unit)| {
+            let weight = match ctx.profile().normalization.unit_aggregation {
+                UnitAggregation::Mean => 1.0 / count as f32,
+                UnitAggregation::Max if Some(index) == best => 1.0,
+                UnitAggregation::Max => 0.0,
+            };
+            unit.with_weight(weight)
+        });
+        let calibrated = calibration.calibrate(raw);
+        let mut explanation = ScoreExplanation::leaf("variant", calibrated);
+        for child in children {
+            explanation = explanation.with_child(child);
+        }
+        if (calibrated - raw).abs() > f32::EPSILON {
+            explanation = explanation.with_note(format!("calibrated from {raw:.4}"));
         }
+        Ok(explanation)
     }
 }
 
 impl AnonymousQueryCompute for MemoryEmbedding {
     type Query = MemoryRetrieveQueryEmbedding;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
-        let tag_score = self.tag().cosine_similarity(query.tag())?;
-        let variant_score = self.variant().anonymous_compute(query.variant())?;
-        Ok(0.4 * tag_score + 0.6 * variant_score)
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
+        if !query.admits(self) {
+            return Ok(
+                ScoreExplanation::leaf("embedding", 0.0).with_note("filtered by hard time span")
+            );
+        }
+        let tag_score = self.tag().cosine_similarity(query.tag())?.clamp(0.0, 1.0);
+        let variant = self.variant().explain(query.variant(), ctx)?;
+        Ok(ScoreExplanation::weighted(
+            "embedding",
+            [
+                (
+                    ctx.profile().note.tag,
+                    Some(ScoreExplanation::leaf("tag", tag_score)),
+                ),
+                (ctx.profile().note.variant, Some(variant)),
+            ],
+        ))
     }
 }
 
-//TODO: take common fields in MemoryNote into computation
-impl AnonymousQueryCompute for EmbeddedMemoryNote {
+//记忆元数据的混合公式：final = similarity * multiplier，
+//multiplier = 1 + w_r(recency - 1) + w_f·frequency + w_a(freshness - 1) + w_fb·feedback，
+//新建、未被提取、无反馈的记忆multiplier恰为1，即保持原相似度；最终分数截断到[0, 1]以保持阈值含义
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoteScoreFormula {
+    pub recency_weight: f32,
+    pub recency_half_life_secs: f32, //距上次访问每经过一个半衰期，recency减半
+    pub frequency_weight: f32,
+    pub frequency_saturation: f32, //frequency = n / (n + saturation)，提取次数达到saturation时为0.5
+    pub age_weight: f32,
+    pub age_half_life_secs: f32, //距创建每经过一个半衰期，freshness减半
+    pub feedback_weight: f32,
+    pub feedback_scale: f32, //feedback = tanh(feedback_score / scale)
+}
+impl Default for NoteScoreFormula {
+    fn default() -> Self {
+        Self {
+            recency_weight: 0.1,
+            recency_half_life_secs: 7.0 * 24.0 * 3600.0,
+            frequency_weight: 0.1,
+            frequency_saturation: 5.0,
+            age_weight: 0.0,
+            age_half_life_secs: 30.0 * 24.0 * 3600.0,
+            feedback_weight: 0.2,
+            feedback_scale: 3.0,
+        }
+    }
+}
+impl NoteScoreFormula {
+    /// 将相似度与记忆的访问时间、提取次数、创建时间以及反馈得分混合为最终分数
+    pub fn blend(
+        &self,
+        similarity: f32,
+        note: &MemoryNote,
+        record: Option<&Record>,
+        now: DateTime<Utc>,
+    ) -> f32 {
+        (similarity * self.multiplier(note, record, now).score()).clamp(0.0, 1.0)
+    }
+    /// 元数据乘子及其各项因子
+    pub fn multiplier(
+        &self,
+        note: &MemoryNote,
+        record: Option<&Record>,
+        now: DateTime<Utc>,
+    ) -> ScoreExplanation {
+        let half_life_decay = |since: DateTime<Utc>, half_life_secs: f32| {
+            let elapsed = (now - since).num_seconds().max(0) as f32;
+            0.5f32.powf(elapsed / half_life_secs.max(1.0))
+        };
+
+        let last_accessed = record
+            .map(|record| record.last_access_time().max(note.last_accessed_time()))
+            .unwrap_or(note.last_accessed_time());
+        let recency = half_life_decay(last_accessed, self.recency_half_life_secs);
+
+        let retrieval_count = record
+            .map(|record| record.retrieval_count().max(note.retrieval_count()))
+            .unwrap_or(note.retrieval_count()) as f32;
+        let frequency =
+            retrieval_count / (retrieval_count + self.frequency_saturation.max(f32::EPSILON));
+
+        let freshness = half_life_decay(note.creation_time(), self.age_half_life_secs);
+
+        let feedback = record
+            .map(|record| {
+                (record.feedback_score() as f32 / self.feedback_scale.max(f32::EPSILON)).tanh()
+            })
+            .unwrap_or(0.0);
+
+        let multiplier = 1.0
+            + self.recency_weight * (recency - 1.0)
+            + self.frequency_weight * frequency
+            + self.age_weight * (freshness - 1.0)
+            + self.feedback_weight * feedback;
+        [
+            ScoreExplanation::leaf("recency", recency).with_weight(self.recency_weight),
+            ScoreExplanation::leaf("frequency", frequency).with_weight(self.frequency_weight),
+            ScoreExplanation::leaf("freshness", freshness).with_weight(self.age_weight),
+            ScoreExplanation::leaf("feedback", feedback).with_weight(self.feedback_weight),
+        ]
+        .into_iter()
+        .fold(
+            ScoreExplanation::leaf("metadata", multiplier.max(0.0)).with_note("multiplier"),
+            ScoreExplanation::with_child,
+        )
+    }
+}
+
+impl AnonymousQueryCompute for EmbeddedMemoryNoteRef<'_> {
     type Query = MemoryRetrieveQueryEmbedding;
-    fn anonymous_compute(&self, query: &Self::Query) -> EmbeddingCalcResult<f32> {
-        self.embedding().anonymous_compute(query)
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
+        let similarity = self.embedding().explain(query, ctx)?;
+        let multiplier = ctx
+            .profile()
+            .formula
+            .multiplier(self.note(), self.record(), ctx.now());
+        let score = (similarity.score() * multiplier.score()).clamp(0.0, 1.0);
+        Ok(ScoreExplanation::leaf("memory", score)
+            .with_child(similarity)
+            .with_child(multiplier))
     }
 }
 
-impl QueryCompute for EmbeddedMemoryNote {
-    fn compute(&self, query: &Self::Query) -> EmbeddingCalcResult<QueryComputeResult> {
+impl QueryCompute for EmbeddedMemoryNoteRef<'_> {
+    fn compute(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<QueryComputeResult> {
         Ok(QueryComputeResult {
             id: self.note().id(),
-            score: self.anonymous_compute(query)?,
+            score: self.anonymous_compute(query, ctx)?,
         })
     }
+    fn compute_explained(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<(QueryComputeResult, ScoreExplanation)> {
+        let explanation = self.explain(query, ctx)?;
+        Ok((
+            QueryComputeResult::new(self.note().id(), explanation.score()),
+            explanation,
+        ))
+    }
+}
+
+impl AnonymousQueryCompute for EmbeddedMemoryNote {
+    type Query = MemoryRetrieveQueryEmbedding;
+    fn explain(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<ScoreExplanation> {
+        self.view().explain(query, ctx)
+    }
+}
+
+impl QueryCompute for EmbeddedMemoryNote {
+    fn compute(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<QueryComputeResult> {
+        self.view().compute(query, ctx)
+    }
+    fn compute_explained(
+        &self,
+        query: &Self::Query,
+        ctx: &ComputeContext<'_>,
+    ) -> EmbeddingCalcResult<(QueryComputeResult, ScoreExplanation)> {
+        self.view().compute_explained(query, ctx)
+    }
 }
 
 #[cfg(test)]
@@ -348,11 +882,191 @@ mod tests {
 
         let query_emb = query.embed(&model).unwrap();
 
-        let score = sem_embedding.anonymous_compute(&query_emb).unwrap();
+        let score = sem_embedding
+            .anonymous_compute(&query_emb, &ComputeContext::new(&ScoringProfile::default()))
+            .unwrap();
         assert!(score > 0.0);
         assert!(score <= 1.0);
     }
 
+    #[test]
+    fn test_scoring_profile_reweights_facets() {
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let sem_embedding = crate::memory::memory_note::sem_mem::SemMemory::new(
+            "咖啡".to_string(),
+            ConceptType::Entity,
+            "一种提神的饮料".to_string(),
+        )
+        .embed(&model)
+        .unwrap();
+
+        let full_query = SemanticQueryUnit::new()
+            .with_concept_identifier("咖啡".to_string())
+            .with_description("下午茶".to_string())
+            .embed(&model)
+            .unwrap();
+        let concept_query = SemanticQueryUnit::new()
+            .with_concept_identifier("咖啡".to_string())
+            .embed(&model)
+            .unwrap();
+
+        let mut profile = ScoringProfile::default();
+        profile.semantic.description = 0.0;
+        let concept_only = sem_embedding
+            .anonymous_compute(
+                &concept_query,
+                &ComputeContext::new(&ScoringProfile::default()),
+            )
+            .unwrap();
+        let reweighted = sem_embedding
+            .anonymous_compute(&full_query, &ComputeContext::new(&profile))
+            .unwrap();
+        assert!((concept_only - reweighted).abs() < 1e-6);
+
+        let default_score = sem_embedding
+            .anonymous_compute(
+                &full_query,
+                &ComputeContext::new(&ScoringProfile::default()),
+            )
+            .unwrap();
+        assert!(default_score < reweighted);
+    }
+
+    #[test]
+    fn test_variant_score_normalized() {
+        use crate::memory::query::retrieve::SituationQueryUnit;
+        use crate::memory::query::scoring::UnitAggregation;
+
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let memory = crate::memory::memory_note::sem_mem::SemMemory::new(
+            "咖啡".to_string(),
+            ConceptType::Entity,
+            "一种提神的饮料".to_string(),
+        );
+        let variant = MemoryEmbeddingVariant::Semantic(memory.embed(&model).unwrap());
+        let unit = SemanticQueryUnit::new().with_concept_identifier("咖啡".to_string());
+        let other = SemanticQueryUnit::new().with_concept_identifier("操场".to_string());
+        let profile = ScoringProfile::default();
+        let ctx = ComputeContext::new(&profile);
+
+        let single = MemoryRetrieveQueryVariant::make_semantic(vec![unit.clone()])
+            .embed(&model)
+            .unwrap();
+        let repeated = MemoryRetrieveQueryVariant::make_semantic(vec![unit.clone(); 5])
+            .embed(&model)
+            .unwrap();
+        let single_score = variant.anonymous_compute(&single, &ctx).unwrap();
+        let repeated_score = variant.anonymous_compute(&repeated, &ctx).unwrap();
+        assert!((single_score - repeated_score).abs() < 1e-6);
+        assert!(repeated_score <= 1.0);
+
+        //多个单元：均值不超过最好的单元，取最大值时等于最好的单元
+        let mixed = MemoryRetrieveQueryVariant::make_semantic(vec![unit, other])
+            .embed(&model)
+            .unwrap();
+        let mean_score = variant.anonymous_compute(&mixed, &ctx).unwrap();
+        let mut max_profile = ScoringProfile::default();
+        max_profile.normalization.unit_aggregation = UnitAggregation::Max;
+        let max_score = variant
+            .anonymous_compute(&mixed, &ComputeContext::new(&max_profile))
+            .unwrap();
+        assert!(mean_score < single_score);
+        assert!((max_score - single_score).abs() < 1e-6);
+
+        //跨类型：语义记忆与情境查询不再恒为0
+        let situation_query = MemoryRetrieveQueryVariant::make_situation(vec![
+            SituationQueryUnit::new().with_narrative("下午喝了一杯咖啡".to_string()),
+        ])
+        .embed(&model)
+        .unwrap();
+        let cross = variant.anonymous_compute(&situation_query, &ctx).unwrap();
+        assert!(cross > 0.0 && cross <= profile.normalization.cross_type);
+    }
+
+    #[test]
+    fn test_explain_mirrors_query_structure() {
+        use crate::memory::memory_note::situation_mem::{
+            Context, SituationType, SpecificSituation,
+        };
+        use crate::memory::memory_note::{MemoryNoteBuilder, MemoryType};
+        use crate::memory::query::retrieve::SituationQueryUnit;
+
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let situation = SpecificSituation::new(
+            "在图书馆和朋友讨论作业".to_string(),
+            Utc::now(),
+            Context::new(
+                Some(Location {
+                    name: "图书馆".to_string(),
+                    coordinates: "学校".to_string(),
+                }),
+                vec![],
+                vec![],
+                vec![],
+                Environment {
+                    atmosphere: "安静".to_string(),
+                    tone: "认真".to_string(),
+                },
+                vec![Event {
+                    action: "讨论".to_string(),
+                    action_intensity: 0.5,
+                    initiator: "我".to_string(),
+                    target: "作业".to_string(),
+                }],
+            ),
+        );
+        let note = MemoryNoteBuilder::new(MemoryType::Situation(SituationType::SpecificSituation(
+            situation,
+        )))
+        .tags(vec!["学习".to_string()])
+        .build()
+        .unwrap()
+        .embed_and_fuse(&model)
+        .unwrap();
+
+        let query = MemoryRetrieveQuery::new(
+            vec!["学习".to_string()],
+            MemoryRetrieveQueryVariant::make_situation(vec![SituationQueryUnit::new()
+                .with_narrative("讨论作业".to_string())
+                .with_location(vec![LocationQueryUnit::new("图书馆")])
+                .with_event(vec![EventQueryUnit::new("讨论")])]),
+        )
+        .embed(&model)
+        .unwrap();
+        let profile = ScoringProfile::default();
+        let ctx = ComputeContext::new(&profile);
+
+        let (result, explanation) = note.compute_explained(&query, &ctx).unwrap();
+        assert_eq!(result.score, explanation.score());
+        assert!((result.score - note.compute(&query, &ctx).unwrap().score).abs() < 1e-6);
+        //同一上下文中的now固定，分数可复现；一个月后未再访问的记忆分数更低
+        assert_eq!(note.compute_explained(&query, &ctx).unwrap().1, explanation);
+        let later = ctx.with_now(ctx.now() + chrono::TimeDelta::days(30));
+        assert!(note.compute(&query, &later).unwrap().score < result.score);
+
+        let situation = explanation.find("specific_situation").unwrap();
+        let facets = situation
+            .children()
+            .iter()
+            .map(|child| child.facet())
+            .collect::<Vec<_>>();
+        assert_eq!(facets, vec!["narrative", "location", "event"]);
+        let contribution_sum = situation
+            .children()
+            .iter()
+            .map(|child| child.contribution())
+            .sum::<f32>();
+        assert!((contribution_sum - situation.score()).abs() < 1e-5);
+        assert!(explanation.find("location").unwrap().find("name").is_some());
+        assert!(explanation.find("participants").is_none());
+        assert!(explanation.find("tag").is_some());
+        assert!(explanation.find("metadata").is_some());
+
+        let rendered = explanation.to_string();
+        assert!(rendered.starts_with("memory: "));
+        assert!(rendered.contains("\n        narrative: "));
+    }
+
     #[test]
     fn test_location_query_compute() {
         let model = BgeSmallZh::default_cpu().unwrap();
@@ -366,7 +1080,12 @@ mod tests {
         let location_query = LocationQueryUnit::new("北京").with_coordinates("中国".to_string());
         let location_query_emb = location_query.embed(&model).unwrap();
 
-        let score = location_emb.anonymous_compute(&location_query_emb).unwrap();
+        let score = location_emb
+            .anonymous_compute(
+                &location_query_emb,
+                &ComputeContext::new(&ScoringProfile::default()),
+            )
+            .unwrap();
         assert!(score > 0.0);
     }
 
@@ -386,7 +1105,10 @@ mod tests {
         let participant_query_emb = participant_query.embed(&model).unwrap();
 
         let score = participant_emb
-            .anonymous_compute(&participant_query_emb)
+            .anonymous_compute(
+                &participant_query_emb,
+                &ComputeContext::new(&ScoringProfile::default()),
+            )
             .unwrap();
         assert!(score > 0.5);
     }
@@ -407,7 +1129,10 @@ mod tests {
         let environment_query_emb = environment_query.embed(&model).unwrap();
 
         let score = environment_emb
-            .anonymous_compute(&environment_query_emb)
+            .anonymous_compute(
+                &environment_query_emb,
+                &ComputeContext::new(&ScoringProfile::default()),
+            )
             .unwrap();
         assert!(score > 0.5);
     }
@@ -429,7 +1154,222 @@ mod tests {
             .with_target("操场".to_string());
         let event_query_emb = event_query.embed(&model).unwrap();
 
-        let score = event_emb.anonymous_compute(&event_query_emb).unwrap();
+        let score = event_emb
+            .anonymous_compute(
+                &event_query_emb,
+                &ComputeContext::new(&ScoringProfile::default()),
+            )
+            .unwrap();
         assert!(score > 0.0);
     }
+
+    #[test]
+    fn test_note_score_formula_blend() {
+        use crate::memory::memory_note::sem_mem::SemMemory;
+        use crate::memory::memory_note::{MemoryNoteBuilder, MemoryType};
+        use crate::memory::record::UserFeedback;
+        use chrono::TimeDelta;
+
+        let now = Utc::now();
+        let note_of = |retrieval_count: usize, last_accessed: DateTime<Utc>| {
+            MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+                "咖啡".to_string(),
+                ConceptType::Entity,
+                "一种饮料".to_string(),
+            )))
+            .retrieval_count(retrieval_count)
+            .create_time(now - TimeDelta::days(60))
+            .last_accessed_time(last_accessed)
+            .build()
+            .unwrap()
+        };
+        let formula = NoteScoreFormula::default();
+
+        //新建、未提取、无反馈的记忆保持原相似度
+        let fresh = MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+            "咖啡".to_string(),
+            ConceptType::Entity,
+            "一种饮料".to_string(),
+        )))
+        .create_time(now)
+        .last_accessed_time(now)
+        .build()
+        .unwrap();
+        assert!((formula.blend(0.8, &fresh, None, now) - 0.8).abs() < 1e-6);
+
+        let stale = note_of(0, now - TimeDelta::days(60));
+        let recalled = note_of(10, now - TimeDelta::hours(1));
+        let mut record = Record::new(recalled.id());
+        record.add_feedback(UserFeedback::Positive);
+        record.add_feedback(UserFeedback::Positive);
+
+        let stale_score = formula.blend(0.8, &stale, None, now);
+        let recalled_score = formula.blend(0.8, &recalled, Some(&record), now);
+        assert!(stale_score < 0.8);
+        assert!(recalled_score > 0.8);
+        assert!(recalled_score > stale_score);
+
+        let mut negative = Record::new(recalled.id());
+        negative.add_feedback(UserFeedback::Negative);
+        assert!(formula.blend(0.8, &recalled, Some(&negative), now) < recalled_score);
+        assert_eq!(formula.blend(0.0, &recalled, Some(&record), now), 0.0);
+    }
+
+    #[test]
+    fn test_situation_time_span_compute() {
+        use crate::memory::memory_note::situation_mem::{Context, SpecificSituation};
+        use crate::memory::query::retrieve::{SituationQueryUnit, TimeSpanQueryUnit};
+        use chrono::{TimeDelta, TimeZone, Utc};
+
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let happened = Utc.with_ymd_and_hms(2024, 6, 8, 10, 0, 0).unwrap();
+        let situation = SpecificSituation::new(
+            "周末和朋友去公园野餐".to_string(),
+            happened,
+            Context::new(
+                None,
+                vec![],
+                vec![],
+                vec![],
+                Environment {
+                    atmosphere: "轻松".to_string(),
+                    tone: "愉快".to_string(),
+                },
+                vec![],
+            ),
+        )
+        .embed(&model)
+        .unwrap();
+
+        let weekend = TimeSpanQueryUnit::new()
+            .with_start(Utc.with_ymd_and_hms(2024, 6, 8, 0, 0, 0).unwrap())
+            .with_end(Utc.with_ymd_and_hms(2024, 6, 9, 23, 59, 59).unwrap());
+        let next_week = TimeSpanQueryUnit::new()
+            .with_start(Utc.with_ymd_and_hms(2024, 6, 10, 0, 0, 0).unwrap())
+            .with_end(Utc.with_ymd_and_hms(2024, 6, 16, 23, 59, 59).unwrap());
+        let query_of = |span: TimeSpanQueryUnit| {
+            SituationQueryUnit::new()
+                .with_narrative("野餐".to_string())
+                .with_time_span(vec![span])
+                .embed(&model)
+                .unwrap()
+        };
+
+        let profile = ScoringProfile::default();
+        let ctx = ComputeContext::new(&profile);
+        let matched = situation
+            .anonymous_compute(&query_of(weekend), &ctx)
+            .unwrap();
+        let untimed = situation
+            .anonymous_compute(
+                &SituationQueryUnit::new()
+                    .with_narrative("野餐".to_string())
+                    .embed(&model)
+                    .unwrap(),
+                &ctx,
+            )
+            .unwrap();
+        assert!(matched > 0.0);
+        assert!((matched - untimed).abs() < 1e-6);
+
+        //硬约束：时间不在区间内直接过滤
+        let hard_miss = situation
+            .anonymous_compute(&query_of(next_week.clone()), &ctx)
+            .unwrap();
+        assert_eq!(hard_miss, 0.0);
+
+        //软约束：距离区间38小时，半衰期一天，分数按指数衰减
+        let soft = query_of(next_week.with_soft_decay(TimeDelta::days(1)));
+        let soft_score = situation.anonymous_compute(&soft, &ctx).unwrap();
+        assert!(soft_score > 0.0 && soft_score < matched);
+        assert!((soft_score - matched * 0.5f32.powf(38.0 / 24.0)).abs() < 1e-4);
+        assert!(soft.admits(None));
+    }
+
+    #[test]
+    fn test_emotion_and_sensory_compute() {
+        use crate::memory::memory_note::situation_mem::{
+            Context, Emotion, SensoryData, SpecificSituation,
+        };
+        use crate::memory::query::retrieve::{
+            EmotionQueryUnit, SensoryQueryUnit, SituationQueryUnit,
+        };
+
+        let model = BgeSmallZh::default_cpu().unwrap();
+        let situation_of = |emotion: &str, intensity: f32| {
+            SpecificSituation::new(
+                "在同学面前念错了名字".to_string(),
+                Utc::now(),
+                Context::new(
+                    None,
+                    vec![],
+                    vec![Emotion {
+                        name: emotion.to_string(),
+                        intensity,
+                    }],
+                    vec![SensoryData {
+                        name: "雨的气味".to_string(),
+                        intensity: 0.5,
+                    }],
+                    Environment {
+                        atmosphere: "安静".to_string(),
+                        tone: "平淡".to_string(),
+                    },
+                    vec![],
+                ),
+            )
+            .embed(&model)
+            .unwrap()
+        };
+        let embarrassed = situation_of("尴尬", 0.9);
+        let mild = situation_of("尴尬", 0.2);
+        let happy = situation_of("开心", 0.9);
+
+        let query_of = |unit: EmotionQueryUnit| {
+            SituationQueryUnit::new()
+                .with_emotion(vec![unit])
+                .embed(&model)
+                .unwrap()
+        };
+        let profile = ScoringProfile::default();
+        let ctx = ComputeContext::new(&profile);
+        let score = |situation: &SpecificSituationEmbedding,
+                     query: &SituationQueryUnitEmbedding| {
+            situation.anonymous_compute(query, &ctx).unwrap()
+        };
+
+        //情绪一致的记忆更容易被回忆，未给出强度时强烈的记忆更突出
+        let any_intensity = query_of(EmotionQueryUnit::new("尴尬"));
+        assert!(score(&embarrassed, &any_intensity) > score(&happy, &any_intensity));
+        assert!(score(&embarrassed, &any_intensity) > score(&mild, &any_intensity));
+
+        //给出强度时按强度的接近程度计分
+        let faint = query_of(EmotionQueryUnit::new("尴尬").with_intensity(0.2));
+        assert!(score(&mild, &faint) > score(&embarrassed, &faint));
+
+        //关闭强度敏感度后只看相似度
+        let mut flat = ScoringProfile::default();
+        flat.intensity.emotion = 0.0;
+        let flat_score = |situation: &SpecificSituationEmbedding| {
+            situation
+                .anonymous_compute(&faint, &ComputeContext::new(&flat))
+                .unwrap()
+        };
+        assert!((flat_score(&mild) - flat_score(&embarrassed)).abs() < 1e-6);
+
+        let sensory_query = SituationQueryUnit::new()
+            .with_sensory(vec![SensoryQueryUnit::new("雨的气味")])
+            .embed(&model)
+            .unwrap();
+        let explanation = embarrassed.explain(&sensory_query, &ctx).unwrap();
+        let sensory = explanation.find("sensory").unwrap();
+        assert!(explanation.find("emotion").is_none());
+        assert!((sensory.score() - explanation.score()).abs() < 1e-6);
+        assert!(
+            (sensory.find("intensity").unwrap().score()
+                - IntensitySensitivity::factor(profile.intensity.sensory, None, 0.5))
+            .abs()
+                < 1e-6
+        );
+    }
 }
@@ -0,0 +1,127 @@
+use std::fmt::{Display, Formatter};
+
+use serde::Serialize;
+
+use crate::memory::query::scoring::weighted_mean;
+
+//分数解释树，结构与查询嵌入的结构对应：每个节点记录一个分量的分数及其在父节点中（归一化后）的权重
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScoreExplanation {
+    facet: &'static str,
+    score: f32,
+    weight: f32, //在父节点中的权重，根节点与乘性因子为1
+    note: Option<String>,
+    children: Vec<ScoreExplanation>,
+}
+impl ScoreExplanation {
+    pub fn leaf(facet: &'static str, score: f32) -> Self {
+        Self {
+            facet,
+            score,
+            weight: 1.0,
+            note: None,
+            children: Vec::new(),
+        }
+    }
+    /// 对给出的(权重, 子分量)做加权平均，缺失的子分量不参与归一化，
+    /// 子节点的weight记录归一化后的权重
+    pub fn weighted(
+        facet: &'static str,
+        parts: impl IntoIterator<Item = (f32, Option<ScoreExplanation>)>,
+    ) -> Self {
+        let parts = parts
+            .into_iter()
+            .filter_map(|(weight, child)| child.map(|child| (weight.max(0.0), child)))
+            .collect::<Vec<_>>();
+        let score = weighted_mean(
+            parts
+                .iter()
+                .map(|(weight, child)| (*weight, Some(child.score))),
+        );
+        let weight_sum = parts.iter().map(|(weight, _)| weight).sum::<f32>();
+        let children = parts
+            .into_iter()
+            .map(|(weight, child)| {
+                let normalized = if weight_sum > 0.0 {
+                    weight / weight_sum
+                } else {
+                    0.0
+                };
+                child.with_weight(normalized)
+            })
+            .collect();
+        Self {
+            facet,
+            score,
+            weight: 1.0,
+            note: None,
+            children,
+        }
+    }
+    pub fn with_score(mut self, score: f32) -> Self {
+        self.score = score;
+        self
+    }
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+    pub fn with_child(mut self, child: ScoreExplanation) -> Self {
+        self.children.push(child);
+        self
+    }
+    pub fn facet(&self) -> &'static str {
+        self.facet
+    }
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+    pub fn children(&self) -> &[ScoreExplanation] {
+        &self.children
+    }
+    /// 该分量对父节点分数的贡献
+    pub fn contribution(&self) -> f32 {
+        self.weight * self.score
+    }
+    /// 深度优先查找第一个指定名称的分量
+    pub fn find(&self, facet: &str) -> Option<&ScoreExplanation> {
+        if self.facet == facet {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(facet))
+    }
+
+    fn fmt_indented(&self, f: &mut Formatter<'_>, depth: usize) -> std::fmt::Result {
+        write!(
+            f,
+            "{:indent$}{}: {:.4} (weight {:.3})",
+            "",
+            self.facet,
+            self.score,
+            self.weight,
+            indent = depth * 2
+        )?;
+        if let Some(note) = &self.note {
+            write!(f, " [{note}]")?;
+        }
+        writeln!(f)?;
+        self.children
+            .iter()
+            .try_for_each(|child| child.fmt_indented(f, depth + 1))
+    }
+}
+impl Display for ScoreExplanation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
@@ -0,0 +1,348 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::memory::{
+    memory_cluster::MemoryCluster,
+    memory_links::LinkKind,
+    memory_note::{
+        sem_mem::ConceptType,
+        situation_mem::{AbstractSituation, SituationType},
+        MemoryId, MemoryKind, MemoryNote, MemoryType,
+    },
+    record::Record,
+};
+
+//抽象情景记忆的种类，对应AbstractSituation的各个变体
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AbstractSituationKind {
+    Location,
+    Participant,
+    Environment,
+    Event,
+}
+impl AbstractSituationKind {
+    pub fn of(situation: &AbstractSituation) -> Self {
+        match situation {
+            AbstractSituation::Location(_) => Self::Location,
+            AbstractSituation::Participant(_) => Self::Participant,
+            AbstractSituation::Environment(_) => Self::Environment,
+            AbstractSituation::Event(_) => Self::Event,
+        }
+    }
+}
+
+//闭区间时间窗口，缺省的一端不设限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct TimeWindow {
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+}
+impl TimeWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_start(mut self, start: DateTime<Utc>) -> Self {
+        self.start = Some(start);
+        self
+    }
+    pub fn with_end(mut self, end: DateTime<Utc>) -> Self {
+        self.end = Some(end);
+        self
+    }
+    pub fn start(&self) -> Option<&DateTime<Utc>> {
+        self.start.as_ref()
+    }
+    pub fn end(&self) -> Option<&DateTime<Utc>> {
+        self.end.as_ref()
+    }
+    pub fn contains(&self, time: &DateTime<Utc>) -> bool {
+        self.start.is_none_or(|start| start <= *time) && self.end.is_none_or(|end| *time <= end)
+    }
+}
+
+//结构化过滤表达式，只描述条件本身而不依赖具体的存储后端；
+//内存中的记忆簇通过matches求值，其他后端可将表达式翻译为自身的过滤语法
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MemoryFilter {
+    All(Vec<MemoryFilter>), //全部满足，空列表恒为真
+    Any(Vec<MemoryFilter>), //任一满足，空列表恒为假
+    Not(Box<MemoryFilter>),
+    Kind(MemoryKind),
+    ConceptType(ConceptType),                 //仅语义记忆可能满足
+    AbstractSituation(AbstractSituationKind), //仅抽象情景记忆可能满足
+    Tag(String),                              //标签精确匹配
+    Participant(String),                      //参与者名称精确匹配
+    CreatedWithin(TimeWindow),
+    AccessedWithin(TimeWindow),
+    MinRetrievalCount(usize),
+    //与id直接相连（任一方向），link_kind为None时不限连接种类
+    LinkedTo {
+        id: MemoryId,
+        link_kind: Option<LinkKind>,
+    },
+}
+impl MemoryFilter {
+    pub fn and(self, other: MemoryFilter) -> Self {
+        match self {
+            Self::All(mut filters) => {
+                filters.push(other);
+                Self::All(filters)
+            }
+            filter => Self::All(vec![filter, other]),
+        }
+    }
+    pub fn or(self, other: MemoryFilter) -> Self {
+        match self {
+            Self::Any(mut filters) => {
+                filters.push(other);
+                Self::Any(filters)
+            }
+            filter => Self::Any(vec![filter, other]),
+        }
+    }
+    pub fn negate(self) -> Self {
+        match self {
+            Self::Not(filter) => *filter,
+            filter => Self::Not(Box::new(filter)),
+        }
+    }
+
+    /// 对单条记忆求值；访问时间与提取次数取记忆本身与访问记录中较新（较大）的值。
+    /// 没有记忆簇时LinkedTo只能检查记忆自身记录的出边
+    pub fn matches(&self, note: &MemoryNote, record: Option<&Record>) -> bool {
+        self.eval(note, record, None)
+    }
+
+    /// 在记忆簇中对记忆求值，访问记录取自记忆簇，LinkedTo检查图中两个方向的边
+    pub fn matches_in(&self, cluster: &MemoryCluster, note: &MemoryNote) -> bool {
+        self.eval(note, cluster.record(note.id()), Some(cluster))
+    }
+
+    fn eval(
+        &self,
+        note: &MemoryNote,
+        record: Option<&Record>,
+        cluster: Option<&MemoryCluster>,
+    ) -> bool {
+        match self {
+            Self::All(filters) => filters
+                .iter()
+                .all(|filter| filter.eval(note, record, cluster)),
+            Self::Any(filters) => filters
+                .iter()
+                .any(|filter| filter.eval(note, record, cluster)),
+            Self::Not(filter) => !filter.eval(note, record, cluster),
+            Self::Kind(kind) => MemoryKind::of(note.mem_type()) == *kind,
+            Self::ConceptType(concept_type) => matches!(
+                note.mem_type(),
+                MemoryType::Semantic(sem) if sem.concept_type == *concept_type
+            ),
+            Self::AbstractSituation(kind) => matches!(
+                note.mem_type(),
+                MemoryType::Situation(SituationType::AbstractSituation(situation))
+                    if AbstractSituationKind::of(situation) == *kind
+            ),
+            Self::Tag(tag) => note.tags().iter().any(|t| t == tag),
+            Self::Participant(name) => Self::has_participant(note, name),
+            Self::CreatedWithin(window) => window.contains(&note.creation_time()),
+            Self::AccessedWithin(window) => {
+                let last_accessed = record
+                    .map(|record| record.last_access_time().max(note.last_accessed_time()))
+                    .unwrap_or(note.last_accessed_time());
+                window.contains(&last_accessed)
+            }
+            Self::MinRetrievalCount(count) => {
+                let retrieval_count = record
+                    .map(|record| record.retrieval_count().max(note.retrieval_count()))
+                    .unwrap_or(note.retrieval_count());
+                retrieval_count >= *count
+            }
+            Self::LinkedTo { id, link_kind } => {
+                let admits = |kind: LinkKind| link_kind.is_none_or(|wanted| wanted == kind);
+                match cluster {
+                    Some(cluster) => cluster
+                        .links_between(note.id(), *id)
+                        .any(|link| admits(link.link_type().kind())),
+                    None => note
+                        .links()
+                        .iter()
+                        .any(|link| link.to() == *id && admits(link.link_type().kind())),
+                }
+            }
+        }
+    }
+
+    fn has_participant(note: &MemoryNote, name: &str) -> bool {
+        match note.mem_type() {
+            MemoryType::Situation(SituationType::SpecificSituation(situation)) => situation
+                .get_context()
+                .get_participants()
+                .iter()
+                .any(|participant| participant.name == name),
+            MemoryType::Situation(SituationType::AbstractSituation(
+                AbstractSituation::Participant(participant),
+            )) => participant.name == name,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::memory::memory_links::{sem_mem::SemMemLink, MemoryLink, MemoryLinkType};
+    use crate::memory::memory_note::{
+        sem_mem::SemMemory,
+        situation_mem::{Context, Environment, Participant, SpecificSituation},
+        MemoryNoteBuilder,
+    };
+
+    fn semantic_note(concept_type: ConceptType, tags: &[&str]) -> MemoryNote {
+        MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+            "咖啡".to_string(),
+            concept_type,
+            "一种饮品".to_string(),
+        )))
+        .tags(tags.iter().map(|t| t.to_string()).collect::<Vec<_>>())
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_filter_memory_type_and_tags() {
+        let entity = semantic_note(ConceptType::Entity, &["饮品", "早晨"]);
+        let idea = semantic_note(ConceptType::Abstract, &["饮品"]);
+        let meeting = MemoryNoteBuilder::new(MemoryType::Situation(
+            SpecificSituation::new(
+                "和小林在咖啡馆见面".to_string(),
+                Utc::now(),
+                Context::new(
+                    None,
+                    vec![Participant {
+                        name: "小林".to_string(),
+                        role: "朋友".to_string(),
+                    }],
+                    vec![],
+                    vec![],
+                    Environment {
+                        atmosphere: "安静".to_string(),
+                        tone: "温和".to_string(),
+                    },
+                    vec![],
+                ),
+            )
+            .into(),
+        ))
+        .build()
+        .unwrap();
+        let friend = MemoryNoteBuilder::new(MemoryType::Situation(
+            AbstractSituation::Participant(Participant {
+                name: "小林".to_string(),
+                role: "朋友".to_string(),
+            })
+            .into(),
+        ))
+        .build()
+        .unwrap();
+
+        let semantic = MemoryFilter::Kind(MemoryKind::Semantic);
+        assert!(semantic.matches(&entity, None));
+        assert!(!semantic.matches(&meeting, None));
+
+        let entity_filter = semantic
+            .clone()
+            .and(MemoryFilter::ConceptType(ConceptType::Entity));
+        assert!(entity_filter.matches(&entity, None));
+        assert!(!entity_filter.matches(&idea, None));
+
+        let morning = MemoryFilter::Tag("早晨".to_string());
+        assert!(morning.matches(&entity, None));
+        assert!(!morning.matches(&idea, None));
+        assert!(morning.clone().negate().matches(&idea, None));
+        assert_eq!(morning.clone().negate().negate(), morning);
+
+        let with_lin = MemoryFilter::Participant("小林".to_string());
+        assert!(with_lin.matches(&meeting, None));
+        assert!(with_lin.matches(&friend, None));
+        assert!(!with_lin.matches(&entity, None));
+
+        let abstract_participant =
+            MemoryFilter::AbstractSituation(AbstractSituationKind::Participant);
+        assert!(abstract_participant.matches(&friend, None));
+        assert!(!abstract_participant.matches(&meeting, None));
+        assert!(abstract_participant.or(semantic).matches(&idea, None));
+
+        assert!(MemoryFilter::All(vec![]).matches(&entity, None));
+        assert!(!MemoryFilter::Any(vec![]).matches(&entity, None));
+    }
+
+    #[test]
+    fn test_filter_time_and_retrieval_count() {
+        let created = Utc.with_ymd_and_hms(2024, 3, 1, 8, 0, 0).unwrap();
+        let note = MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+            "咖啡".to_string(),
+            ConceptType::Entity,
+            "一种饮品".to_string(),
+        )))
+        .create_time(created)
+        .last_accessed_time(created)
+        .retrieval_count(1)
+        .build()
+        .unwrap();
+
+        let march = TimeWindow::new()
+            .with_start(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap())
+            .with_end(Utc.with_ymd_and_hms(2024, 3, 31, 23, 59, 59).unwrap());
+        assert!(MemoryFilter::CreatedWithin(march).matches(&note, None));
+        assert!(MemoryFilter::AccessedWithin(march).matches(&note, None));
+
+        //访问记录中的提取会更新访问时间与提取次数
+        let mut record = Record::new(note.id());
+        record.record_retrieval();
+        record.record_retrieval();
+        assert!(!MemoryFilter::AccessedWithin(march).matches(&note, Some(&record)));
+        assert!(!MemoryFilter::MinRetrievalCount(2).matches(&note, None));
+        assert!(MemoryFilter::MinRetrievalCount(2).matches(&note, Some(&record)));
+
+        let filter: MemoryFilter = serde_json::from_str(
+            &serde_json::to_string(&MemoryFilter::CreatedWithin(march)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(filter, MemoryFilter::CreatedWithin(march));
+    }
+
+    #[test]
+    fn test_filter_linked_to_without_cluster() {
+        let target = semantic_note(ConceptType::Entity, &[]);
+        let id = MemoryId::new();
+        let note = MemoryNoteBuilder::new(MemoryType::Semantic(SemMemory::new(
+            "拿铁".to_string(),
+            ConceptType::Entity,
+            "加奶的咖啡".to_string(),
+        )))
+        .id(id)
+        .mem_links(vec![MemoryLink::new(
+            id,
+            target.id(),
+            MemoryLinkType::Sem(SemMemLink::new("属于".to_string(), 1.0, 1.0)),
+        )])
+        .build()
+        .unwrap();
+
+        //没有记忆簇时只检查记忆自身的出边
+        let to_target = |link_kind| MemoryFilter::LinkedTo {
+            id: target.id(),
+            link_kind,
+        };
+        assert!(to_target(None).matches(&note, None));
+        assert!(to_target(Some(LinkKind::Semantic)).matches(&note, None));
+        assert!(!to_target(Some(LinkKind::Procedure)).matches(&note, None));
+        assert!(!MemoryFilter::LinkedTo {
+            id,
+            link_kind: None,
+        }
+        .matches(&target, None));
+    }
+}
@@ -1,6 +1,10 @@
-use chrono::{DateTime, Utc};
+use std::hash::{Hash, Hasher};
+
+use chrono::{DateTime, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::memory::query::filter::MemoryFilter;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PrioritizedMemoryRetrieveQuery {
     priority: u32, //优先级将决定最终混合一个MemoryNote的检索分数时的权重
@@ -26,14 +30,29 @@ impl PrioritizedMemoryRetrieveQuery {
 pub struct MemoryRetrieveQuery {
     tag: Vec<String>,
     variant: MemoryRetrieveQueryVariant,
+    filter: Option<MemoryFilter>, //结构化硬过滤，不满足的记忆不参与计分
 }
 impl MemoryRetrieveQuery {
+    pub fn new(tag: impl Into<Vec<String>>, variant: MemoryRetrieveQueryVariant) -> Self {
+        MemoryRetrieveQuery {
+            tag: tag.into(),
+            variant,
+            filter: None,
+        }
+    }
+    pub fn with_filter(mut self, filter: MemoryFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
     pub fn tag(&self) -> &[String] {
         &self.tag
     }
     pub fn variant(&self) -> &MemoryRetrieveQueryVariant {
         &self.variant
     }
+    pub fn filter(&self) -> Option<&MemoryFilter> {
+        self.filter.as_ref()
+    }
     pub fn with_priority(self, priority: u32) -> PrioritizedMemoryRetrieveQuery {
         PrioritizedMemoryRetrieveQuery::new(priority, self)
     }
@@ -104,6 +123,8 @@ pub struct SituationQueryUnit {
     time_span: Option<Vec<TimeSpanQueryUnit>>,
     environment: Option<EnvironmentQueryUnit>,
     event: Option<Vec<EventQueryUnit>>,
+    emotion: Option<Vec<EmotionQueryUnit>>,
+    sensory: Option<Vec<SensoryQueryUnit>>,
 }
 impl SituationQueryUnit {
     pub fn new() -> Self {
@@ -114,6 +135,8 @@ impl SituationQueryUnit {
             time_span: None,
             environment: None,
             event: None,
+            emotion: None,
+            sensory: None,
         }
     }
     pub fn with_location(mut self, location: Vec<LocationQueryUnit>) -> Self {
@@ -140,6 +163,14 @@ impl SituationQueryUnit {
         self.narrative = Some(narrative);
         self
     }
+    pub fn with_emotion(mut self, emotion: Vec<EmotionQueryUnit>) -> Self {
+        self.emotion = Some(emotion);
+        self
+    }
+    pub fn with_sensory(mut self, sensory: Vec<SensoryQueryUnit>) -> Self {
+        self.sensory = Some(sensory);
+        self
+    }
     pub fn narrative(&self) -> Option<&String> {
         self.narrative.as_ref()
     }
@@ -158,6 +189,12 @@ impl SituationQueryUnit {
     pub fn event(&self) -> Option<&Vec<EventQueryUnit>> {
         self.event.as_ref()
     }
+    pub fn emotion(&self) -> Option<&Vec<EmotionQueryUnit>> {
+        self.emotion.as_ref()
+    }
+    pub fn sensory(&self) -> Option<&Vec<SensoryQueryUnit>> {
+        self.sensory.as_ref()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -273,16 +310,137 @@ impl EventQueryUnit {
     }
 }
 
+//情绪查询单元，如“感到尴尬的时候”；给出强度时按强度的接近程度计分，否则强度越高的记忆越突出
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmotionQueryUnit {
+    name: String,
+    intensity: Option<f32>,
+}
+impl EmotionQueryUnit {
+    pub fn new(name: impl Into<String>) -> Self {
+        EmotionQueryUnit {
+            name: name.into(),
+            intensity: None,
+        }
+    }
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = Some(intensity.clamp(0.0, 1.0));
+        self
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn intensity(&self) -> Option<f32> {
+        self.intensity
+    }
+}
+//强度按位比较，使查询单元可以作为键使用
+impl PartialEq for EmotionQueryUnit {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.intensity.map(f32::to_bits) == other.intensity.map(f32::to_bits)
+    }
+}
+impl Eq for EmotionQueryUnit {}
+impl Hash for EmotionQueryUnit {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.intensity.map(f32::to_bits).hash(state);
+    }
+}
+
+//感官查询单元，如“雨的气味”，强度的处理与情绪相同
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensoryQueryUnit {
+    name: String,
+    intensity: Option<f32>,
+}
+impl SensoryQueryUnit {
+    pub fn new(name: impl Into<String>) -> Self {
+        SensoryQueryUnit {
+            name: name.into(),
+            intensity: None,
+        }
+    }
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = Some(intensity.clamp(0.0, 1.0));
+        self
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn intensity(&self) -> Option<f32> {
+        self.intensity
+    }
+}
+impl PartialEq for SensoryQueryUnit {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.intensity.map(f32::to_bits) == other.intensity.map(f32::to_bits)
+    }
+}
+impl Eq for SensoryQueryUnit {}
+impl Hash for SensoryQueryUnit {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.intensity.map(f32::to_bits).hash(state);
+    }
+}
+
+//时间区间的匹配方式：硬过滤要求时间落在区间内；软衰减按与区间的距离指数衰减
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TimeSpanMatch {
+    Hard,
+    Soft { half_life_secs: u64 }, //距离区间每增加一个半衰期，分数减半
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TimeSpanQueryUnit {
     start: Option<DateTime<Utc>>,
     end: Option<DateTime<Utc>>,
+    matching: TimeSpanMatch,
 }
 impl TimeSpanQueryUnit {
     pub fn new() -> Self {
         TimeSpanQueryUnit {
             start: None,
             end: None,
+            matching: TimeSpanMatch::Hard,
+        }
+    }
+    pub fn with_soft_decay(mut self, half_life: TimeDelta) -> Self {
+        self.matching = TimeSpanMatch::Soft {
+            half_life_secs: half_life.num_seconds().max(1) as u64,
+        };
+        self
+    }
+    pub fn matching(&self) -> TimeSpanMatch {
+        self.matching
+    }
+    pub fn is_hard(&self) -> bool {
+        self.matching == TimeSpanMatch::Hard
+    }
+    pub fn contains(&self, time: &DateTime<Utc>) -> bool {
+        self.start.is_none_or(|start| start <= *time) && self.end.is_none_or(|end| *time <= end)
+    }
+    /// 时间与区间的距离（秒），落在区间内时为0
+    pub fn distance_secs(&self, time: &DateTime<Utc>) -> i64 {
+        match (self.start, self.end) {
+            (Some(start), _) if *time < start => (start - *time).num_seconds(),
+            (_, Some(end)) if *time > end => (*time - end).num_seconds(),
+            _ => 0,
+        }
+    }
+    /// 时间的匹配分数，范围[0, 1]
+    pub fn score(&self, time: &DateTime<Utc>) -> f32 {
+        if self.contains(time) {
+            return 1.0;
+        }
+        match self.matching {
+            TimeSpanMatch::Hard => 0.0,
+            TimeSpanMatch::Soft { half_life_secs } => {
+                0.5f32.powf(self.distance_secs(time) as f32 / half_life_secs as f32)
+            }
         }
     }
     pub fn with_start(mut self, start: DateTime<Utc>) -> Self {
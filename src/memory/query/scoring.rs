@@ -0,0 +1,382 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::memory::query::compute::NoteScoreFormula;
+
+//与角色文件放在同一目录下的计分配置文件名
+pub const SCORING_PROFILE_FILE: &str = "scoring.toml";
+
+//查询计分的全部权重配置，缺省字段使用默认值
+//各组权重在计算时只对查询中实际给出的分量归一化，因此只需关心相对大小
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoringProfile {
+    pub note: NoteWeights,
+    pub semantic: SemanticWeights,
+    pub situation: SituationWeights,
+    pub location: LocationWeights,
+    pub participant: ParticipantWeights,
+    pub environment: EnvironmentWeights,
+    pub event: EventWeights,
+    pub intensity: IntensitySensitivity,
+    pub formula: NoteScoreFormula,
+    pub normalization: NormalizationProfile,
+}
+impl ScoringProfile {
+    pub fn from_toml_str(content: &str) -> Result<Self, ScoringProfileError> {
+        Ok(toml::from_str(content)?)
+    }
+    pub fn from_toml_file(path: impl AsRef<Path>) -> Result<Self, ScoringProfileError> {
+        Self::from_toml_str(&std::fs::read_to_string(path)?)
+    }
+    /// 读取角色文件同目录下的scoring.toml，文件不存在时使用默认配置
+    pub fn load_beside(character_file: impl AsRef<Path>) -> Result<Self, ScoringProfileError> {
+        let path = character_file
+            .as_ref()
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(SCORING_PROFILE_FILE);
+        if path.exists() {
+            Self::from_toml_file(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoteWeights {
+    pub tag: f32,
+    pub variant: f32,
+}
+impl Default for NoteWeights {
+    fn default() -> Self {
+        Self {
+            tag: 0.4,
+            variant: 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SemanticWeights {
+    pub concept_main: f32,  //概念与记忆主体内容
+    pub concept_alias: f32, //概念与记忆别名
+    pub concept: f32,
+    pub description: f32,
+}
+impl Default for SemanticWeights {
+    fn default() -> Self {
+        Self {
+            concept_main: 0.7,
+            concept_alias: 0.3,
+            concept: 0.5,
+            description: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SituationWeights {
+    pub narrative: f32,
+    pub location: f32,
+    pub participants: f32,
+    pub environment: f32,
+    pub event: f32,
+    pub emotion: f32,
+    pub sensory: f32,
+}
+impl Default for SituationWeights {
+    fn default() -> Self {
+        Self {
+            narrative: 1.0,
+            location: 1.0,
+            participants: 1.0,
+            environment: 1.0,
+            event: 1.0,
+            emotion: 1.0,
+            sensory: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocationWeights {
+    pub name: f32,
+    pub coordinates: f32,
+}
+impl Default for LocationWeights {
+    fn default() -> Self {
+        Self {
+            name: 0.6,
+            coordinates: 0.4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParticipantWeights {
+    pub name: f32,
+    pub role: f32,
+}
+impl Default for ParticipantWeights {
+    fn default() -> Self {
+        Self {
+            name: 0.6,
+            role: 0.4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentWeights {
+    pub atmosphere: f32,
+    pub tone: f32,
+}
+impl Default for EnvironmentWeights {
+    fn default() -> Self {
+        Self {
+            atmosphere: 0.5,
+            tone: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventWeights {
+    pub action: f32,
+    pub initiator: f32,
+    pub target: f32,
+}
+impl Default for EventWeights {
+    fn default() -> Self {
+        Self {
+            action: 0.4,
+            initiator: 0.3,
+            target: 0.3,
+        }
+    }
+}
+
+//情绪与感官分数受强度影响的程度，范围[0, 1]：0表示只看相似度，1表示完全按强度匹配程度缩放
+//查询给出强度时匹配程度为两者的接近程度，否则为记忆自身的强度（强烈的情绪更容易被回忆）
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IntensitySensitivity {
+    pub emotion: f32,
+    pub sensory: f32,
+}
+impl Default for IntensitySensitivity {
+    fn default() -> Self {
+        Self {
+            emotion: 0.3,
+            sensory: 0.3,
+        }
+    }
+}
+impl IntensitySensitivity {
+    /// 强度因子，范围[1 - sensitivity, 1]；记忆端的融合强度为各项强度之和，截断到[0, 1]
+    pub fn factor(sensitivity: f32, query: Option<f32>, memory: f32) -> f32 {
+        let sensitivity = sensitivity.clamp(0.0, 1.0);
+        let memory = memory.clamp(0.0, 1.0);
+        let matched = query.map_or(memory, |query| 1.0 - (query - memory).abs());
+        1.0 - sensitivity + sensitivity * matched
+    }
+}
+
+//多个查询单元的分数如何合并为一个分数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitAggregation {
+    #[default]
+    Mean,
+    Max,
+}
+impl UnitAggregation {
+    /// 合并各单元的分数，没有任何分数时返回None
+    pub fn aggregate(&self, scores: impl IntoIterator<Item = f32>) -> Option<f32> {
+        let (sum, max, count) = scores.into_iter().fold(
+            (0.0f32, f32::NEG_INFINITY, 0usize),
+            |(sum, max, count), score| (sum + score, max.max(score), count + 1),
+        );
+        if count == 0 {
+            return None;
+        }
+        Some(match self {
+            Self::Mean => sum / count as f32,
+            Self::Max => max,
+        })
+    }
+}
+
+//将原始分数线性映射到[0, 1]：floor及以下为0，ceiling及以上为1
+//不同记忆类型的相似度分布不同，可分别设置以使阈值对各类型含义一致
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoreCalibration {
+    pub floor: f32,
+    pub ceiling: f32,
+}
+impl Default for ScoreCalibration {
+    fn default() -> Self {
+        Self {
+            floor: 0.0,
+            ceiling: 1.0,
+        }
+    }
+}
+impl ScoreCalibration {
+    pub fn calibrate(&self, raw: f32) -> f32 {
+        if self.ceiling <= self.floor {
+            return raw.clamp(0.0, 1.0);
+        }
+        ((raw - self.floor) / (self.ceiling - self.floor)).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NormalizationProfile {
+    pub unit_aggregation: UnitAggregation,
+    pub semantic: ScoreCalibration,
+    pub situation: ScoreCalibration,
+    pub cross_type: f32, //语义记忆与情境查询（或反之）跨类型比较时的折扣系数
+}
+impl Default for NormalizationProfile {
+    fn default() -> Self {
+        Self {
+            unit_aggregation: UnitAggregation::default(),
+            semantic: ScoreCalibration::default(),
+            situation: ScoreCalibration::default(),
+            cross_type: 0.8,
+        }
+    }
+}
+
+/// 对给出的(权重, 分数)做加权平均，分数为None的分量不参与归一化；
+/// 没有任何有效分量或权重和为0时返回0
+pub fn weighted_mean(scores: impl IntoIterator<Item = (f32, Option<f32>)>) -> f32 {
+    let (total, weight_sum) = scores
+        .into_iter()
+        .filter_map(|(weight, score)| score.map(|score| (weight.max(0.0), score)))
+        .fold((0.0, 0.0), |(total, weight_sum), (weight, score)| {
+            (total + weight * score, weight_sum + weight)
+        });
+    if weight_sum > 0.0 {
+        total / weight_sum
+    } else {
+        0.0
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ScoringProfileError {
+    #[error("Failed to read scoring profile: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse scoring profile: {0}")]
+    Toml(#[from] toml::de::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scoring_profile_partial_toml() {
+        let profile = ScoringProfile::from_toml_str(
+            r#"
+            [note]
+            tag = 0.2
+            variant = 0.8
+
+            [situation]
+            event = 3.0
+            location = 0.5
+
+            [formula]
+            feedback_weight = 0.0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(profile.note.tag, 0.2);
+        assert_eq!(profile.situation.event, 3.0);
+        assert_eq!(profile.situation.narrative, 1.0);
+        assert_eq!(profile.semantic, SemanticWeights::default());
+        assert_eq!(profile.formula.feedback_weight, 0.0);
+        assert_eq!(
+            profile.formula.recency_weight,
+            NoteScoreFormula::default().recency_weight
+        );
+        assert!(ScoringProfile::from_toml_str("[note]\ntag = \"high\"").is_err());
+    }
+
+    #[test]
+    fn test_scoring_profile_load_beside_character() {
+        let dir = std::env::temp_dir().join(format!("soul_mem_scoring_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let character = dir.join("character.toml");
+        assert_eq!(
+            ScoringProfile::load_beside(&character).unwrap(),
+            ScoringProfile::default()
+        );
+
+        std::fs::write(dir.join(SCORING_PROFILE_FILE), "[event]\naction = 1.0\n").unwrap();
+        let profile = ScoringProfile::load_beside(&character).unwrap();
+        assert_eq!(profile.event.action, 1.0);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let example = ScoringProfile::load_beside("character_example/example.toml").unwrap();
+        assert_eq!(example.situation.event, 2.0);
+    }
+
+    #[test]
+    fn test_normalization_helpers() {
+        assert_eq!(UnitAggregation::Mean.aggregate([0.2, 0.4, 0.9]), Some(0.5));
+        assert_eq!(UnitAggregation::Max.aggregate([0.2, 0.4, 0.9]), Some(0.9));
+        assert_eq!(UnitAggregation::Mean.aggregate([]), None);
+
+        let calibration = ScoreCalibration {
+            floor: 0.3,
+            ceiling: 0.8,
+        };
+        assert_eq!(calibration.calibrate(0.2), 0.0);
+        assert!((calibration.calibrate(0.55) - 0.5).abs() < 1e-6);
+        assert_eq!(calibration.calibrate(0.95), 1.0);
+        assert_eq!(ScoreCalibration::default().calibrate(-0.3), 0.0);
+
+        let profile = ScoringProfile::from_toml_str(
+            "[normalization]\nunit_aggregation = \"max\"\n[normalization.situation]\nfloor = 0.2\n",
+        )
+        .unwrap();
+        assert_eq!(profile.normalization.unit_aggregation, UnitAggregation::Max);
+        assert_eq!(profile.normalization.situation.floor, 0.2);
+        assert_eq!(profile.normalization.situation.ceiling, 1.0);
+    }
+
+    #[test]
+    fn test_intensity_factor() {
+        assert_eq!(IntensitySensitivity::factor(0.0, Some(1.0), 0.0), 1.0);
+        assert!((IntensitySensitivity::factor(0.5, Some(0.8), 0.8) - 1.0).abs() < 1e-6);
+        assert!((IntensitySensitivity::factor(0.5, Some(1.0), 0.2) - 0.6).abs() < 1e-6);
+        //未给出期望强度时偏好强烈的记忆，融合强度超过1时按1计
+        assert!((IntensitySensitivity::factor(0.4, None, 0.5) - 0.8).abs() < 1e-6);
+        assert_eq!(IntensitySensitivity::factor(0.4, None, 1.7), 1.0);
+    }
+
+    #[test]
+    fn test_weighted_mean_skips_missing() {
+        assert_eq!(weighted_mean([(0.6, Some(1.0)), (0.4, None)]), 1.0);
+        assert!((weighted_mean([(3.0, Some(1.0)), (1.0, Some(0.0))]) - 0.75).abs() < 1e-6);
+        assert_eq!(weighted_mean([(1.0, None)]), 0.0);
+        assert_eq!(weighted_mean([(0.0, Some(1.0))]), 0.0);
+    }
+}
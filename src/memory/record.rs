@@ -14,7 +14,7 @@ pub enum UserFeedback {
 }
 
 // 记忆访问记录
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Record {
     memory_id: MemoryId,
     // 提取次数
@@ -54,11 +54,28 @@ impl Record {
         let now = Utc::now();
         self.feedback_history.insert(now, feedback.clone());
 
-        // 待定，目前是加减1
+        self.feedback_score += Self::feedback_delta(&feedback);
+    }
+
+    // 合并同一记忆在另一处的访问记录：提取次数取较大者，访问时间取并集，反馈历史合并后重新计分
+    pub fn merge(&mut self, other: Record) {
+        self.retrieval_count = self.retrieval_count.max(other.retrieval_count);
+        self.first_access_time = self.first_access_time.min(other.first_access_time);
+        self.last_access_time = self.last_access_time.max(other.last_access_time);
+        self.feedback_history.extend(other.feedback_history);
+        self.feedback_score = self
+            .feedback_history
+            .values()
+            .map(Self::feedback_delta)
+            .sum();
+    }
+
+    // 待定，目前是加减1
+    fn feedback_delta(feedback: &UserFeedback) -> i32 {
         match feedback {
-            UserFeedback::Positive => self.feedback_score += 1,
-            UserFeedback::Negative => self.feedback_score -= 1,
-            UserFeedback::Neutral | UserFeedback::None => {}
+            UserFeedback::Positive => 1,
+            UserFeedback::Negative => -1,
+            UserFeedback::Neutral | UserFeedback::None => 0,
         }
     }
 
@@ -144,6 +161,7 @@ mod tests {
     // 10. 综合测试 - 测试多个操作的组合使用
     // 11. Record 序列化 - 测试 Record 结构体的序列化和反序列化
     // 12. 边界情况 - 测试空反馈历史等边界情况
+    // 13. merge() - 测试合并同一记忆的两份访问记录
     //
     // 所有测试均通过，验证了 record.rs 的核心功能正常工作。
 
@@ -458,4 +476,35 @@ mod tests {
         let feedback_before = record.feedback_history_before(now);
         assert_eq!(feedback_before.len(), 0);
     }
+
+    // 测试 13: merge() - 测试合并同一记忆的两份访问记录
+    #[test]
+    fn test_record_merge() {
+        let memory_id = create_test_memory_id();
+        let mut record = Record::new(memory_id);
+        record.record_retrieval();
+        record.add_feedback(UserFeedback::Positive);
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let mut other = Record::new(memory_id);
+        for _ in 0..3 {
+            other.record_retrieval();
+        }
+        other.add_feedback(UserFeedback::Negative);
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        other.add_feedback(UserFeedback::Negative);
+
+        let first_access_time = record.first_access_time();
+        let last_access_time = other.last_access_time();
+        record.merge(other);
+
+        // 提取次数取较大者，访问时间取并集
+        assert_eq!(record.retrieval_count(), 3);
+        assert_eq!(record.first_access_time(), first_access_time);
+        assert_eq!(record.last_access_time(), last_access_time);
+
+        // 反馈历史合并后重新计分：+1 -1 -1 = -1
+        assert_eq!(record.feedback_history_after(first_access_time).len(), 3);
+        assert_eq!(record.feedback_score(), -1);
+    }
 }
@@ -1,9 +1,31 @@
-//use crate::memory::memory_cluster::MemoryCluster;
+use crate::memory::memory_cluster::MemoryCluster;
+use crate::memory::working_memory::sliding_window::SlidingWindow;
 
 //代表工作记忆，应当包含记忆子图，短期记忆（滑动窗口），记忆的提取记录等。
 // 占位，后续逐渐增加内容
 pub struct WorkingMemory {
-    //cluster: MemoryCluster,
+    cluster: MemoryCluster,
+    sliding_window: SlidingWindow,
+}
+impl WorkingMemory {
+    pub fn new(cluster: MemoryCluster, sliding_window: SlidingWindow) -> Self {
+        Self {
+            cluster,
+            sliding_window,
+        }
+    }
+    pub fn cluster(&self) -> &MemoryCluster {
+        &self.cluster
+    }
+    pub fn cluster_mut(&mut self) -> &mut MemoryCluster {
+        &mut self.cluster
+    }
+    pub fn sliding_window(&self) -> &SlidingWindow {
+        &self.sliding_window
+    }
+    pub fn sliding_window_mut(&mut self) -> &mut SlidingWindow {
+        &mut self.sliding_window
+    }
 }
 pub mod sliding_window;
 pub mod llm;
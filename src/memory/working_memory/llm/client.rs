@@ -24,6 +24,13 @@ impl LlmClient {
         let response = self.client.chat().create(request).await?;
         Ok(self.unstructured(response))
     }
+    /// 调用LLM并将第一条回复解析为JSON，回复中包裹JSON的代码块或多余文字会被忽略
+    pub async fn call_llm_json<R: DeserializeOwned, T: PromptBuilder>(&self, content: &mut T) -> Result<R> {
+        let replies = self.call_llm(content).await?;
+        let reply = replies.first().context("LLM returned no choices")?;
+        serde_json::from_str(extract_json(reply))
+            .with_context(|| format!("LLM reply is not valid JSON: {reply}"))
+    }
     pub fn structured<T: PromptBuilder>(&self, content: &mut T) -> Result<CreateChatCompletionRequest> {
         let messages = content.build_prompt();
         let request = CreateChatCompletionRequestArgs::default()
@@ -42,3 +49,23 @@ impl LlmClient {
             .collect()
     }
 }
+
+//截取回复中第一个'{'或'['到最后一个'}'或']'之间的内容
+pub(crate) fn extract_json(reply: &str) -> &str {
+    match (reply.find(['{', '[']), reply.rfind(['}', ']'])) {
+        (Some(start), Some(end)) if start <= end => &reply[start..=end],
+        _ => reply,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_json() {
+        assert_eq!(extract_json("```json\n{\"a\": 1}\n```"), "{\"a\": 1}");
+        assert_eq!(extract_json("result: [1, 2]"), "[1, 2]");
+        assert_eq!(extract_json("no json"), "no json");
+    }
+}
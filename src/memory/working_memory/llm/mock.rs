@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::{client::LlmClient, config::LLMConfig};
+
+//测试用的本地OpenAI兼容服务器，按顺序返回预设的回复，并记录收到的请求体
+pub struct MockLlmServer {
+    base: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+impl MockLlmServer {
+    pub async fn start(replies: Vec<String>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/v1", listener.local_addr().unwrap());
+        let replies = Arc::new(Mutex::new(VecDeque::from(replies)));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let requests_handle = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let replies = replies.clone();
+                let requests = requests_handle.clone();
+                tokio::spawn(async move {
+                    let _ = Self::handle(stream, replies, requests).await;
+                });
+            }
+        });
+        Self { base, requests }
+    }
+    pub fn client(&self) -> LlmClient {
+        LlmClient::new(LLMConfig::new("mock-key", &self.base, "mock-model"))
+    }
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    async fn handle(
+        mut stream: TcpStream,
+        replies: Arc<Mutex<VecDeque<String>>>,
+        requests: Arc<Mutex<Vec<String>>>,
+    ) -> std::io::Result<()> {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let header_end = loop {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            buf.extend_from_slice(&chunk[..n]);
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+        };
+        let content_length = String::from_utf8_lossy(&buf[..header_end])
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        while buf.len() < header_end + content_length {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        requests
+            .lock()
+            .unwrap()
+            .push(String::from_utf8_lossy(&buf[header_end..]).to_string());
+
+        let reply = replies.lock().unwrap().pop_front();
+        let (status, body) = match reply {
+            Some(content) => (
+                "200 OK",
+                json!({
+                    "id": "mock",
+                    "object": "chat.completion",
+                    "created": 0,
+                    "model": "mock-model",
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": content },
+                        "finish_reason": "stop"
+                    }]
+                }),
+            ),
+            None => (
+                "400 Bad Request",
+                json!({
+                    "error": {
+                        "message": "no more mock replies",
+                        "type": "invalid_request_error",
+                        "param": null,
+                        "code": null
+                    }
+                }),
+            ),
+        };
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+}
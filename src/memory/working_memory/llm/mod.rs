@@ -1,3 +1,5 @@
 pub mod config;
 pub mod prompt;
 pub mod client;
+#[cfg(test)]
+pub mod mock;
@@ -1,4 +1,28 @@
-use async_openai::types::chat::ChatCompletionRequestMessage;
+use async_openai::types::chat::{
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessage,
+    ChatCompletionRequestUserMessage,
+};
+use std::mem::take;
 pub trait PromptBuilder {
     fn build_prompt(&mut self) -> Vec<ChatCompletionRequestMessage>;
 }
+
+//由一条系统提示和一条用户消息组成的单轮提示词
+pub struct ChatPrompt {
+    messages: Vec<ChatCompletionRequestMessage>,
+}
+impl ChatPrompt {
+    pub fn new(system: &str, user: &str) -> Self {
+        Self {
+            messages: vec![
+                ChatCompletionRequestSystemMessage::from(system).into(),
+                ChatCompletionRequestUserMessage::from(user).into(),
+            ],
+        }
+    }
+}
+impl PromptBuilder for ChatPrompt {
+    fn build_prompt(&mut self) -> Vec<ChatCompletionRequestMessage> {
+        take(&mut self.messages)
+    }
+}
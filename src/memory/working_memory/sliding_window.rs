@@ -71,6 +71,15 @@ impl SlidingWindow {
         self.window.get(index)
     }
 
+    //按滑入顺序遍历窗口中的信息
+    pub fn iter(&self) -> impl Iterator<Item = &Information> {
+        self.window.iter()
+    }
+    //获取当前的摘要记忆
+    pub async fn summary(&self) -> String {
+        self.summary.read().await.get_previous_summary()
+    }
+
     //判断窗口是否为空
     pub fn is_empty(&self) -> bool {
         self.window.is_empty()